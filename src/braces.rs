@@ -0,0 +1,134 @@
+// src/braces.rs
+//! Shell-style brace expansion of inputs, e.g. `src/{main,lib}.rs`.
+
+/// Expand every `{a,b}` in `pattern` combinatorially, like the shell does,
+/// including nested braces such as `{a,{b,c}}`. Braces without a comma, like
+/// `{a}`, are kept literally, as are `\{`, `\}` and `\,`. An unmatched `{`
+/// is an error, as is expanding to more than `max` strings, which is caught
+/// before they're all built.
+pub fn expand(pattern: &str, max: usize) -> anyhow::Result<Vec<String>> {
+    expand_part(pattern, max).map_err(|e| match e {
+        Error::Unmatched => anyhow::anyhow!("unmatched `{{` in `{pattern}`"),
+        Error::TooMany => anyhow::anyhow!(
+            "`{pattern}` expands to more than {max} paths; raise --max-expansions to allow it"
+        ),
+    })
+}
+
+enum Error {
+    Unmatched,
+    TooMany,
+}
+
+fn expand_part(s: &str, max: usize) -> Result<Vec<String>, Error> {
+    let Some(open) = find_unescaped(s, 0, '{') else {
+        return Ok(vec![unescape(s)]);
+    };
+    let close = matching_close(s, open).ok_or(Error::Unmatched)?;
+    let prefix = unescape(&s[..open]);
+    let inner = &s[open + 1..close];
+
+    let branches = split_top_level(inner);
+    let alternatives: Vec<String> = if branches.len() == 1 {
+        expand_part(inner, max)?
+            .into_iter()
+            .map(|alt| format!("{{{alt}}}"))
+            .collect()
+    } else {
+        let mut alternatives = Vec::new();
+        for branch in branches {
+            alternatives.extend(expand_part(branch, max)?);
+            if alternatives.len() > max {
+                return Err(Error::TooMany);
+            }
+        }
+        alternatives
+    };
+    let suffixes = expand_part(&s[close + 1..], max)?;
+    if alternatives.len().saturating_mul(suffixes.len()) > max {
+        return Err(Error::TooMany);
+    }
+
+    let mut out = Vec::with_capacity(alternatives.len() * suffixes.len());
+    for alt in &alternatives {
+        for suffix in &suffixes {
+            out.push(format!("{prefix}{alt}{suffix}"));
+        }
+    }
+    Ok(out)
+}
+
+/// Byte index of the first `target` at or after `from` not preceded by `\`.
+fn find_unescaped(s: &str, from: usize, target: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s[from..].char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == target => return Some(from + i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Byte index of the `}` closing the `{` at `open`.
+fn matching_close(s: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut escaped = false;
+    for (i, c) in s[open..].char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split `s` on the commas that aren't escaped or inside nested braces.
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Drop the backslash from `\{`, `\}` and `\,`; other backslashes stay, so
+/// Windows paths pass through untouched.
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(&next @ ('{' | '}' | ','))) => {
+                out.push(next);
+                chars.next();
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,29 @@
+// src/checksum.rs
+//! Content digests for `--inline-checksum` and the seen cache.
+
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Checksum {
+    Crc32,
+    Sha256,
+}
+
+impl Checksum {
+    /// Render the digest of `data` as `<algo>:<hex>`.
+    pub fn label(self, data: &[u8]) -> String {
+        match self {
+            Checksum::Crc32 => format!("crc32:{:08x}", crc32fast::hash(data)),
+            Checksum::Sha256 => format!("sha256:{}", sha256_hex(data)),
+        }
+    }
+}
+
+/// Hex SHA-256 of `data`.
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
@@ -0,0 +1,53 @@
+// src/diff.rs
+//! Unified diff rendering for `--diff`.
+
+use similar::{ChangeTag, TextDiff};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Render a unified diff of `old` against `new`. Returns an empty string when
+/// the two are identical. With `color` off the output is plain `+`/`-` text
+/// suitable for `patch`.
+pub fn unified(old_name: &str, old: &str, new_name: &str, new: &str, color: bool) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let paint = |code: &str, text: &str| {
+        if color {
+            format!("{code}{text}{RESET}")
+        } else {
+            text.to_string()
+        }
+    };
+
+    let mut out = String::new();
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        if out.is_empty() {
+            out.push_str(&paint(BOLD, &format!("--- {old_name}")));
+            out.push('\n');
+            out.push_str(&paint(BOLD, &format!("+++ {new_name}")));
+            out.push('\n');
+        }
+        out.push_str(&paint(CYAN, &hunk.header().to_string()));
+        out.push('\n');
+        for change in hunk.iter_changes() {
+            let (sign, code) = match change.tag() {
+                ChangeTag::Delete => ("-", Some(RED)),
+                ChangeTag::Insert => ("+", Some(GREEN)),
+                ChangeTag::Equal => (" ", None),
+            };
+            let line = format!("{sign}{}", change.value().trim_end_matches('\n'));
+            match code {
+                Some(code) => out.push_str(&paint(code, &line)),
+                None => out.push_str(&line),
+            }
+            out.push('\n');
+            if change.missing_newline() {
+                out.push_str("\\ No newline at end of file\n");
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,53 @@
+// src/edit.rs
+//! `--edit`: opening the bundle in the user's editor.
+
+use std::io::{self, IsTerminal, Write};
+use std::process::Command;
+
+/// Can an editor take over the terminal? Only when both stdin and stdout
+/// are one.
+pub fn interactive() -> bool {
+    #[cfg(debug_assertions)]
+    if crate::test_hooks::assume_terminal() {
+        return true;
+    }
+    io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+/// The editor to use: `$VISUAL`, then `$EDITOR`, then `vi`.
+fn editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|cmd| !cmd.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// Write `bundle` to a new temporary file, `fpr-<random>.<ext>`, open it
+/// in the editor and wait for that to exit, then delete the file. The file
+/// is created fresh, never through an existing path or symlink. The editor
+/// command may carry arguments, as in `code --wait`, split on whitespace.
+pub fn open(bundle: &[u8], ext: &str) -> anyhow::Result<()> {
+    let mut file = tempfile::Builder::new()
+        .prefix("fpr-")
+        .suffix(&format!(".{ext}"))
+        .tempfile()
+        .map_err(|e| anyhow::anyhow!("cannot create a temporary file: {e}"))?;
+    file.write_all(bundle)
+        .and_then(|()| file.flush())
+        .map_err(|e| anyhow::anyhow!("cannot write `{}`: {e}", file.path().display()))?;
+
+    let editor = editor();
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = Command::new(program)
+        .args(words)
+        .arg(file.path())
+        .status()
+        .map_err(|e| anyhow::anyhow!("cannot run editor `{editor}`: {e}"))?;
+    if !status.success() {
+        anyhow::bail!("editor `{editor}` exited with {status}");
+    }
+    file.close()
+        .map_err(|e| anyhow::anyhow!("cannot remove the temporary file: {e}"))
+}
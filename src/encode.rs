@@ -0,0 +1,43 @@
+// src/encode.rs
+//! `--output-encoding`: re-encoding the final output for non-UTF-8 targets.
+
+use encoding_rs::{EncoderResult, Encoding};
+
+/// Parse an encoding label such as `latin1` or `shift_jis`, rejecting the
+/// encodings `encoding_rs` can only decode (UTF-16 and `replacement`).
+pub fn parse_encoding(label: &str) -> Result<&'static Encoding, String> {
+    let encoding = Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| format!("unknown encoding `{label}`"))?;
+    if encoding.output_encoding() != encoding {
+        return Err(format!("`{}` can't be used for output", encoding.name()));
+    }
+    Ok(encoding)
+}
+
+/// `text` in `encoding`, with each character it can't represent replaced by
+/// `?`, along with how many were replaced.
+pub fn encode(text: &str, encoding: &'static Encoding) -> (Vec<u8>, usize) {
+    let mut encoder = encoding.new_encoder();
+    let capacity = encoder
+        .max_buffer_length_from_utf8_without_replacement(text.len())
+        .unwrap_or(text.len());
+    let mut out = Vec::with_capacity(capacity);
+    let mut buf = vec![0; capacity.max(16)];
+    let mut replaced = 0;
+    let mut rest = text;
+    loop {
+        let (result, read, written) =
+            encoder.encode_from_utf8_without_replacement(rest, &mut buf, true);
+        out.extend_from_slice(&buf[..written]);
+        rest = &rest[read..];
+        match result {
+            EncoderResult::InputEmpty => break,
+            EncoderResult::OutputFull => {}
+            EncoderResult::Unmappable(_) => {
+                out.push(b'?');
+                replaced += 1;
+            }
+        }
+    }
+    (out, replaced)
+}
@@ -0,0 +1,159 @@
+// src/git.rs
+//! Thin wrappers around the `git` command line.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use regex::Regex;
+
+use crate::lines::Span;
+
+/// Run `git` in `dir` and return its stdout, turning a non-zero exit into an
+/// error carrying git's own message.
+fn git(dir: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|e| anyhow::anyhow!("failed to run git: {e}"))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Split `path` into the directory to run git in and the name to pass it.
+fn dir_and_name(path: &Path) -> (&Path, &Path) {
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    (dir, path.file_name().map(Path::new).unwrap_or(path))
+}
+
+/// Lines of `path` changed relative to `HEAD` (staged or not), as 0-based
+/// spans on the working-tree side. A pure deletion shows up as an empty span
+/// at the point of removal. Returns `None` if git reports no changes, which
+/// includes untracked files.
+pub fn changed_spans(path: &Path) -> anyhow::Result<Option<Vec<Span>>> {
+    let (dir, name) = dir_and_name(path);
+    let name = name.to_string_lossy();
+    let diff = git(
+        dir,
+        &["diff", "--no-color", "--unified=0", "HEAD", "--", &name],
+    )?;
+
+    let hunk = Regex::new(r"^@@ -\d+(?:,\d+)? \+(\d+)(?:,(\d+))? @@").expect("valid regex");
+    let spans: Vec<Span> = diff
+        .lines()
+        .filter_map(|line| hunk.captures(line))
+        .map(|caps| {
+            let start: usize = caps[1].parse().unwrap_or(0);
+            let count: usize = caps.get(2).map_or(1, |c| c.as_str().parse().unwrap_or(0));
+            if count == 0 {
+                // `+N,0` means "deleted after line N".
+                start..start
+            } else {
+                start - 1..start - 1 + count
+            }
+        })
+        .collect();
+    Ok((!spans.is_empty()).then_some(spans))
+}
+
+/// Is `dir` inside a git working tree?
+pub fn in_work_tree(dir: &Path) -> bool {
+    git(dir, &["rev-parse", "--is-inside-work-tree"]).is_ok_and(|out| out.trim() == "true")
+}
+
+/// Every file tracked in the repository containing `dir`, as absolute paths
+/// under the (canonical) top-level directory git reports, including those
+/// tracked in its submodules when `submodules` is set.
+pub fn tracked_files(dir: &Path, submodules: bool) -> anyhow::Result<HashSet<PathBuf>> {
+    let top = PathBuf::from(git(dir, &["rev-parse", "--show-toplevel"])?.trim());
+    let args: &[&str] = match submodules {
+        true => &["ls-files", "-z", "--recurse-submodules"],
+        false => &["ls-files", "-z"],
+    };
+    let listing = git(&top, args)?;
+    Ok(listing
+        .split('\0')
+        .filter(|name| !name.is_empty())
+        .map(|name| top.join(name))
+        .collect())
+}
+
+/// The submodule directories listed in the nearest `.gitmodules` at or
+/// above `dir`, canonicalized. Read as text, so nothing needs to be checked
+/// out or even committed; a missing or unreadable file lists none.
+pub fn submodules(dir: &Path) -> HashSet<PathBuf> {
+    let Ok(dir) = dir.canonicalize() else {
+        return HashSet::new();
+    };
+    let Some(top) = dir.ancestors().find(|d| d.join(".gitmodules").is_file()) else {
+        return HashSet::new();
+    };
+    let Ok(text) = std::fs::read_to_string(top.join(".gitmodules")) else {
+        return HashSet::new();
+    };
+    text.lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            (key.trim() == "path").then(|| value.trim())
+        })
+        .filter_map(|path| top.join(path).canonicalize().ok())
+        .collect()
+}
+
+/// Author name of the last commit touching `path`, or `None` when it has
+/// no history: it's untracked, uncommitted or outside a repository.
+pub fn last_author(path: &Path) -> Option<String> {
+    let (dir, name) = dir_and_name(path);
+    let out = git(
+        dir,
+        &["log", "-1", "--format=%an", "--", &name.to_string_lossy()],
+    )
+    .ok()?;
+    let author = out.trim();
+    (!author.is_empty()).then(|| author.to_string())
+}
+
+/// `git blame` for `path` as one `<short hash> <author>` label per line, or
+/// `None` when git can't blame it (e.g. the file is untracked).
+pub fn blame(path: &Path) -> Option<Vec<String>> {
+    let (dir, name) = dir_and_name(path);
+    let porcelain = git(
+        dir,
+        &["blame", "--porcelain", "--", &name.to_string_lossy()],
+    )
+    .ok()?;
+
+    let mut authors: HashMap<&str, &str> = HashMap::new();
+    let mut current = "";
+    let mut line_commits = Vec::new();
+    for line in porcelain.lines() {
+        if line.starts_with('\t') {
+            line_commits.push(current);
+        } else if let Some(author) = line.strip_prefix("author ") {
+            authors.entry(current).or_insert(author);
+        } else if let Some(hash) = line.split(' ').next().filter(|h| is_hash(h)) {
+            current = hash;
+        }
+    }
+    Some(
+        line_commits
+            .into_iter()
+            .map(|hash| format!("{} {}", &hash[..8], authors.get(hash).unwrap_or(&"?")))
+            .collect(),
+    )
+}
+
+fn is_hash(s: &str) -> bool {
+    s.len() == 40 && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
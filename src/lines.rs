@@ -0,0 +1,162 @@
+// src/lines.rs
+//! Line-based slicing of file content.
+
+use std::ops::Range;
+
+use regex::Regex;
+
+/// A half-open range of 0-based line indices.
+pub type Span = Range<usize>;
+
+/// Spans covering every line that matches `re`, widened by `context` lines on
+/// each side and merged where they overlap or touch.
+pub fn match_spans(content: &str, re: &Regex, context: usize) -> Vec<Span> {
+    let spans = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| re.is_match(line))
+        .map(|(i, _)| i..i + 1)
+        .collect();
+    widen(spans, context)
+}
+
+/// Grow each span by `context` lines on both sides, then merge.
+pub fn widen(spans: Vec<Span>, context: usize) -> Vec<Span> {
+    merge(
+        spans
+            .into_iter()
+            .map(|s| s.start.saturating_sub(context)..s.end + context)
+            .collect(),
+    )
+}
+
+/// Whole-file `spans` cut down to the lines `within` selects, counting
+/// from its first line. An empty span (a pure deletion) is kept if it falls
+/// inside or at either edge.
+pub fn clip(spans: Vec<Span>, within: Span) -> Vec<Span> {
+    spans
+        .into_iter()
+        .filter(|s| match s.is_empty() {
+            true => within.start <= s.start && s.start <= within.end,
+            false => s.start < within.end && s.end > within.start,
+        })
+        .map(|s| s.start.max(within.start) - within.start..s.end.min(within.end) - within.start)
+        .collect()
+}
+
+/// Sort spans and merge any that overlap or are directly adjacent.
+pub fn merge(mut spans: Vec<Span>) -> Vec<Span> {
+    spans.sort_by_key(|s| s.start);
+    let mut out: Vec<Span> = Vec::new();
+    for span in spans.into_iter().filter(|s| !s.is_empty()) {
+        match out.last_mut() {
+            Some(last) if span.start <= last.end => last.end = last.end.max(span.end),
+            _ => out.push(span),
+        }
+    }
+    out
+}
+
+/// Keep only the lines inside `spans` (sorted, non-overlapping), printing a
+/// `… (K lines omitted) …` marker wherever lines between two spans are dropped.
+pub fn select(content: &str, spans: &[Span]) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let mut out = String::new();
+    let mut prev_end: Option<usize> = None;
+    for span in spans {
+        let end = span.end.min(lines.len());
+        if span.start >= end {
+            continue;
+        }
+        if let Some(prev_end) = prev_end {
+            if span.start > prev_end {
+                out.push_str(&omitted_marker(span.start - prev_end));
+            }
+        }
+        for line in &lines[span.start..end] {
+            out.push_str(line);
+        }
+        prev_end = Some(end);
+    }
+    out
+}
+
+/// The first `n` lines of `content`.
+pub fn head(content: &str, n: usize) -> String {
+    content.split_inclusive('\n').take(n).collect()
+}
+
+/// Keep the first and last `keep / 2` lines (the head gets the extra line
+/// when `keep` is odd), eliding the middle. Short files are returned as-is.
+pub fn truncate_middle(content: &str, keep: usize) -> String {
+    let total = content.split_inclusive('\n').count();
+    if total <= keep {
+        return content.to_string();
+    }
+    let tail = keep / 2;
+    let head = keep - tail;
+    select(content, &[0..head, total - tail..total])
+}
+
+/// Content shorter than this is never taken for minified, however long its
+/// lines.
+const MINIFIED_MIN_BYTES: usize = 1024;
+
+/// Heuristic for --skip-minified: is `content` at least
+/// `MINIFIED_MIN_BYTES` long, with lines averaging more than `threshold`
+/// bytes? Minifiers put a whole file on one or a few lines.
+pub fn looks_minified(content: &str, threshold: usize) -> bool {
+    content.len() >= MINIFIED_MIN_BYTES
+        && content.len() / content.lines().count().max(1) > threshold
+}
+
+/// The marker line standing in for `n` elided lines.
+pub fn omitted_marker(n: usize) -> String {
+    let noun = if n == 1 { "line" } else { "lines" };
+    format!("… ({n} {noun} omitted) …\n")
+}
+
+/// A `path:START-END` selection: 1-based and inclusive, open-ended without
+/// an END.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: Option<usize>,
+}
+
+impl LineRange {
+    /// Split a trailing `:START-END` (or `:START-`) off `input`. Anything
+    /// else, including a Windows drive's `C:`, is left alone.
+    pub fn split(input: &str) -> anyhow::Result<(&str, Option<LineRange>)> {
+        let re = Regex::new(r"^(.+):(\d+)-(\d*)$").expect("valid regex");
+        let Some(caps) = re.captures(input) else {
+            return Ok((input, None));
+        };
+        let start: usize = caps[2].parse()?;
+        let end: Option<usize> = match &caps[3] {
+            "" => None,
+            end => Some(end.parse()?),
+        };
+        if start == 0 || end.is_some_and(|end| end < start) {
+            anyhow::bail!(
+                "invalid line range in `{input}`: lines are numbered from 1, START to END"
+            );
+        }
+        let path = caps.get(1).expect("group 1 always matches").as_str();
+        Ok((path, Some(LineRange { start, end })))
+    }
+
+    /// The 0-based span of lines selected.
+    pub fn span(self) -> Span {
+        self.start - 1..self.end.unwrap_or(usize::MAX)
+    }
+}
+
+impl std::fmt::Display for LineRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.end {
+            Some(end) => write!(f, "{}-{end}", self.start),
+            None => write!(f, "{}-", self.start),
+        }
+    }
+}
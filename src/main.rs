@@ -1,155 +1,2994 @@
 // src/main.rs
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
 
-use clap::Parser;
-use globwalk::GlobWalkerBuilder;
-use walkdir::WalkDir;
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
+use encoding_rs::Encoding;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::WalkBuilder;
+use rayon::prelude::*;
+use regex::Regex;
+
+use crate::checksum::{sha256_hex, Checksum};
+use crate::lines::LineRange;
+use crate::output::{header_name, ExtStats, Printer};
+use crate::parallel::{CloseOnDrop, InOrder, ReadBudget};
+use crate::read::{read_decoded, read_file, read_lossy};
+use crate::seen::SeenCache;
+use crate::state::RunState;
+use crate::timing::Phase;
+
+mod braces;
+mod checksum;
+mod diff;
+mod edit;
+mod encode;
+mod git;
+mod lines;
+mod notebook;
+mod output;
+mod parallel;
+mod patch;
+mod progress;
+mod read;
+mod secrets;
+mod seen;
+mod select;
+mod shell;
+mod state;
+mod symbol;
+#[cfg(debug_assertions)]
+mod test_hooks;
+mod testcode;
+mod timing;
+mod tokens;
+mod transform;
+mod tree;
+mod unbundle;
+mod watch;
+
+/// Directories pruned by `--exclude-vcs`.
+const VCS_DIRS: &[&str] = &[".git", ".svn", ".hg", ".bzr"];
+
+/// File names dropped by `--skip-lockfiles`.
+const LOCKFILES: &[&str] = &[
+    "Cargo.lock",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "poetry.lock",
+    "Pipfile.lock",
+    "Gemfile.lock",
+    "composer.lock",
+    "go.sum",
+];
+
+/// Printed in place of a binary file's content, unless --quiet-binary.
+const BINARY_PLACEHOLDER: &str = "[binary file skipped]\n";
+
+/// Exit status used when `--timeout` fires, matching coreutils `timeout`.
+const TIMEOUT_EXIT_CODE: i32 = 124;
 
 /// Simple file‑print utility (`fpr`).
 ///
 /// Supports:
 /// * Plain paths
 /// * Shell‑style globs (`*.rs`, `**/*.txt`, etc.)
+/// * A leading `~` and `$VAR` / `${VAR}`, expanded first, as a shell would;
+///   an unset variable is an error.
+/// * Shell‑style braces, e.g. `src/{main,lib}.rs`, expanded before anything
+///   else but those; `\{` keeps a brace literal.
+/// * Line ranges, e.g. `main.rs:10-40` or `main.rs:10-`, printing just those
+///   (1-based, inclusive) lines.
 /// * **Rust‑like grouping** with parentheses and commas, e.g.
 ///   `src/(main.rs, lib.rs, util/(fs, time), -tests)`.
 ///     * `-item` or `^item` inside a group **excludes** that path.
 ///     * Nesting is allowed.
-///     * Assume `(`, `)`, and `,` do not appear in actual filenames.
+///     * Exclusions win by default; with `--group-precedence include-wins`,
+///       `src/(gen, -gen/out, gen/out/keep.rs)` still prints `keep.rs`.
+///     * Escape a literal `(`, `)` or `,` in a filename with a backslash,
+///       e.g. `dir/(a.txt, report\(final\).txt)` (not on Windows, where `\`
+///       separates paths).
 #[derive(Parser, Debug)]
-#[command(author, version, about)]
+#[command(
+    author,
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    group = ArgGroup::new("handoff").args(["clipboard", "edit"])
+)]
 struct Cli {
-    /// Paths, globs, or grouped patterns to print
-    #[arg(required = true)]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Paths, globs, or grouped patterns to print; `-` reads paths from stdin
+    #[arg(required_unless_present_any = ["files_from", "lists_dir"])]
     inputs: Vec<String>,
 
+    /// Also read inputs from FILE, one per line
+    #[arg(long, value_name = "FILE")]
+    files_from: Option<PathBuf>,
+
+    /// Treat every `*.txt` in DIR as a --files-from list and write one bundle
+    /// per list into --output-dir
+    #[arg(long, value_name = "DIR", requires = "output_dir")]
+    lists_dir: Option<PathBuf>,
+
+    /// Instead of printing contents, print a table of each file's bytes,
+    /// lines and estimated tokens (about four characters each), largest
+    /// first, with a total
+    #[arg(long)]
+    count: bool,
+
+    /// Instead of the contents, print a breakdown of the files by extension:
+    /// how many, and their bytes and lines, largest first. With --stats, the
+    /// contents are printed as usual and the breakdown of what was printed
+    /// follows the --stats summary
+    #[arg(long, conflicts_with = "count")]
+    ext_stats: bool,
+
+    /// Only check that every file is valid UTF-8, listing those that aren't
+    /// with the offset of the first bad byte; exits nonzero if any fail
+    #[arg(long)]
+    check_utf8: bool,
+
+    /// Write one bundle per top-level directory into --output-dir as
+    /// `<dir>.txt`; files outside any directory go to `root.txt`
+    #[arg(long, requires = "output_dir", conflicts_with = "lists_dir")]
+    split_by_dir: bool,
+
+    /// Copy the bundle to the system clipboard instead of printing it
+    #[arg(short = 'c', long, conflicts_with_all = ["output", "output_encoding"])]
+    clipboard: bool,
+
+    /// Open the bundle in `$VISUAL` (or `$EDITOR`, or `vi`) instead of
+    /// printing it, through a temporary file deleted once the editor exits.
+    /// Without a terminal to edit in, the bundle is printed as usual
+    #[arg(long, conflicts_with_all = ["output", "output_encoding"])]
+    edit: bool,
+
+    /// With --clipboard or --edit, print the bundle to stdout as well
+    #[arg(long, requires = "handoff")]
+    tee: bool,
+
+    /// After printing, keep watching the files (and the directories holding
+    /// them, for new matches) and print everything again whenever they
+    /// change, clearing the terminal in between; Ctrl-C stops
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "clipboard", "edit", "diff", "lists_dir", "diff_against", "check_utf8", "count",
+            "ext_stats", "tree_json", "split_by_dir", "interactive",
+        ]
+    )]
+    watch: bool,
+
+    /// Write the bundle to FILE instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Encode the output as ENCODING (e.g. `latin1`, `shift_jis`) instead
+    /// of UTF-8, for the bundle and any files written to --output-dir
+    #[arg(long, value_name = "ENCODING", value_parser = encode::parse_encoding)]
+    output_encoding: Option<&'static Encoding>,
+
+    /// What to do about characters --output-encoding can't represent
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value_t = Unencodable::Replace,
+        requires = "output_encoding"
+    )]
+    on_unencodable: Unencodable,
+
+    /// Directory that receives the bundles written by --lists-dir or
+    /// --split-by-dir
+    #[arg(long, value_name = "DIR")]
+    output_dir: Option<PathBuf>,
+
     /// Separator printed between files (default: "---")
     #[arg(long, default_value = "---")]
     separator: String,
 
-    /// Recurse into sub‑directories when an input is a directory
-    #[arg(short, long, default_value_t = true)]
-    recursive: bool,
+    /// Which side wins when a group branch includes a path another branch
+    /// excludes. `include-wins` keeps files named by a plain-path include
+    /// at or beneath the exclusion: in `(src, -src/gen, src/gen/keep.rs)`
+    /// it keeps `keep.rs` while still dropping the rest of `src/gen`
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = Precedence::ExcludeWins)]
+    group_precedence: Precedence,
+
+    /// Most paths one input may expand to through `{a,b}` braces and
+    /// `(...)` groups together; combining them multiplies, so past this an
+    /// input is refused before it's fully expanded
+    #[arg(
+        long,
+        alias = "max-expansion",
+        value_name = "N",
+        default_value_t = 10_000
+    )]
+    max_expansions: usize,
+
+    /// Header line template; `{path}` is replaced with the file's name
+    /// (default: "=== {path} ===")
+    #[arg(long, value_name = "TEMPLATE")]
+    header_format: Option<String>,
+
+    /// Pad each `=== path ===` header so the closing `===` lines up across
+    /// files, one column past the longest path (and its --inline-checksum)
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "header_format", "single", "anonymize_paths", "show_encoding", "state_file",
+        ]
+    )]
+    pad_headers: bool,
+
+    /// Wrap each file's content in a Markdown code fence tagged with the
+    /// language its extension suggests
+    #[arg(long)]
+    markdown: bool,
+
+    /// With --markdown, wrap each file's fenced content in a collapsible
+    /// `<details>` block whose summary gives its path and size
+    #[arg(long, requires = "markdown")]
+    collapsible: bool,
+
+    /// Print everything as one continuous stream, as if it were a single
+    /// file: each file is introduced by just a `// --- path ---` line (or the
+    /// --header-format line), with no separators or blank lines between
+    #[arg(
+        long,
+        conflicts_with_all = ["as_patch", "markdown", "split_markers", "index_footer", "json", "list"]
+    )]
+    single: bool,
+
+    /// Fence language for files ending in `.EXT`, as `EXT=LANG`; with
+    /// `:TAG`, print only what's inside their `<TAG>` blocks, e.g.
+    /// `vue=ts:script` (repeatable)
+    #[arg(long, value_name = "EXT=LANG[:TAG]", value_parser = parse_content_type)]
+    content_type_map: Vec<ContentType>,
+
+    /// Number each separator with the position of the file after it and the
+    /// total printed, e.g. `--- [3/12] ---`; nothing is printed until every
+    /// file has been read, so the total is known
+    #[arg(long)]
+    numbered_separators: bool,
+
+    /// Only print the separator where the file extension changes
+    #[arg(long)]
+    quiet_separators: bool,
+
+    /// Show each file relative to the directory input it was found under
+    #[arg(long)]
+    relative_to_input: bool,
+
+    /// Remove the first N path components from displayed paths
+    #[arg(long, value_name = "N")]
+    strip_components: Option<usize>,
+
+    /// Show paths relative to the project root found by walking up from the
+    /// current directory to the nearest --root-markers entry
+    #[arg(long)]
+    smart_root: bool,
+
+    /// File or directory names that mark a project root for --smart-root
+    #[arg(
+        long,
+        value_name = "NAMES",
+        value_delimiter = ',',
+        default_value = "Cargo.toml,package.json,.git"
+    )]
+    root_markers: Vec<String>,
+
+    /// Skip files matched by `.gitignore` rules when walking directories and
+    /// expanding globs (the default)
+    #[arg(long, overrides_with = "no_gitignore")]
+    gitignore: bool,
+
+    /// Walk everything, ignoring `.gitignore` rules
+    #[arg(long, overrides_with = "gitignore")]
+    no_gitignore: bool,
+
+    /// Recurse into sub‑directories when an input is a directory
+    #[arg(short, long, default_value_t = true)]
+    recursive: bool,
+
+    /// Descend at most N levels below a directory input, or below the
+    /// current directory for globs; files directly inside are at depth 1
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Follow symbolic links while walking directories and globs; without
+    /// it, links a walk comes across are skipped. Links named directly are
+    /// always read
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// Treat a detected symlink loop as a fatal error instead of a warning
+    #[arg(long, requires = "follow_symlinks")]
+    abort_on_symlink_loop: bool,
+
+    /// Drop every collected file matching GLOB, checked against both its
+    /// relative and absolute path (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Exclude files matched by this gitignore-syntax file instead of the
+    /// nearest `.fprignore` in the current directory or above
+    #[arg(long, value_name = "PATH", conflicts_with = "no_ignore_file")]
+    ignore_file: Option<PathBuf>,
+
+    /// Don't look for a `.fprignore`
+    #[arg(long)]
+    no_ignore_file: bool,
+
+    /// Skip directories with this name while walking (repeatable)
+    #[arg(long, value_name = "NAME")]
+    exclude_dir: Vec<String>,
+
+    /// Skip version-control metadata directories (`.git`, `.svn`, `.hg`, `.bzr`)
+    #[arg(long)]
+    exclude_vcs: bool,
+
+    /// Walk into git submodules, the directories `.gitmodules` lists, which
+    /// are otherwise skipped as they aren't part of the superproject; each
+    /// is walked with its own `.gitignore`. With --tracked-only, files
+    /// tracked in a submodule count as tracked
+    #[arg(long)]
+    submodules: bool,
+
+    /// Descend into hidden (dot) directories when walking
+    #[arg(long)]
+    hidden_dirs: bool,
+
+    /// Include hidden (dot) files found when walking
+    #[arg(long)]
+    hidden_files: bool,
+
+    /// Order files by `path`, newest `mtime` first, largest `size` first, or
+    /// not at all (`none`: the order their inputs and group branches were
+    /// given, with walks and globs sorted within their input)
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = SortMode::Path)]
+    sort: SortMode,
+
+    /// Reverse the --sort order
+    #[arg(long)]
+    reverse: bool,
+
+    /// Same as `--sort none`
+    #[arg(long, conflicts_with = "sort")]
+    preserve_order: bool,
+
+    /// Within each directory, list subdirectories before files, both in
+    /// --tree and in the order files are printed
+    #[arg(long, conflicts_with = "preserve_order")]
+    sort_dirs_before_files: bool,
+
+    /// Only include files of a predefined language set (repeatable)
+    #[arg(long, value_enum, value_name = "SET")]
+    lang: Vec<Lang>,
+
+    /// Only include files with this extension, e.g. `rs` or `.toml`
+    /// (repeatable, case-insensitive); files without one are left out
+    #[arg(long, value_name = "EXT")]
+    ext: Vec<String>,
+
+    /// Apply --exclude, --lang, --ext, --skip-lockfiles, --exclude-tests,
+    /// --tracked-only and the author filters to files named directly on the
+    /// command line too, instead of always printing them
+    #[arg(long)]
+    strict_filters: bool,
+
+    /// Only include files tracked by git (as `git ls-files` lists them)
+    #[arg(long)]
+    tracked_only: bool,
+
+    /// Only include files whose last commit (per `git log -1`) was by NAME
+    #[arg(long, value_name = "NAME", conflicts_with = "not_authored_by")]
+    authored_by: Option<String>,
+
+    /// Leave out files whose last commit (per `git log -1`) was by NAME
+    #[arg(long, value_name = "NAME")]
+    not_authored_by: Option<String>,
+
+    /// What --authored-by and --not-authored-by do with files git has no
+    /// history for, such as untracked files or ones outside a repository
+    #[arg(long, value_enum, value_name = "ACTION", default_value_t = Unauthored::Exclude)]
+    unauthored: Unauthored,
+
+    /// Skip dependency lockfiles: Cargo.lock, package-lock.json,
+    /// npm-shrinkwrap.json, yarn.lock, pnpm-lock.yaml, poetry.lock,
+    /// Pipfile.lock, Gemfile.lock, composer.lock and go.sum
+    #[arg(long)]
+    skip_lockfiles: bool,
+
+    /// Another file name for --skip-lockfiles to skip (repeatable)
+    #[arg(long, value_name = "NAME", requires = "skip_lockfiles")]
+    lockfile: Vec<String>,
+
+    /// Leave out test code, judged by path: anything under a `tests/`,
+    /// `test/`, `__tests__/` or `spec(s)/` directory (below the directory
+    /// input, or the current directory, not above it), files named like
+    /// `*_test.*`, `test_*.*`, `*.test.*`, `*.spec.*` or `*_spec.*`, Rust's
+    /// `tests.rs` and `conftest.py`; plus Rust files marked `#![cfg(test)]`.
+    /// Names alone can't catch everything, nor tell fixtures from tests
+    #[arg(long)]
+    exclude_tests: bool,
+
+    /// Keep at most N files of each extension, the first N in --sort order,
+    /// once every other filter has run; named files count too
+    #[arg(long, value_name = "N")]
+    max_per_ext: Option<usize>,
+
+    /// Skip files whose content hash is recorded in FILE, then record the
+    /// hashes of everything printed
+    #[arg(long, value_name = "FILE")]
+    seen_cache: Option<PathBuf>,
+
+    /// Forget everything in --seen-cache before this run
+    #[arg(long, requires = "seen_cache")]
+    reset_cache: bool,
+
+    /// Print only files that are new or changed since the run that last
+    /// used FILE, noting which in their headers, and list the files gone
+    /// since then first; then record this run in FILE. Binary files aren't
+    /// tracked
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["json", "list", "as_patch", "split_markers"]
+    )]
+    state_file: Option<PathBuf>,
+
+    /// Pick which of the resolved files to print from a terminal checklist
+    #[arg(short, long)]
+    interactive: bool,
+
+    /// Go ahead with --interactive even when an input like a bare `**`
+    /// would sweep up everything under the current directory
+    #[arg(long)]
+    yes: bool,
+
+    /// Skip files with more than N lines
+    #[arg(long, value_name = "N")]
+    max_lines: Option<usize>,
+
+    /// Skip files that look minified, with a warning: at least 1 KiB whose
+    /// lines average more than --minified-line-threshold bytes
+    #[arg(long)]
+    skip_minified: bool,
+
+    /// The average line length, in bytes, above which --skip-minified
+    /// takes a file to be minified
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 300,
+        requires = "skip_minified"
+    )]
+    minified_line_threshold: usize,
+
+    /// Keep at most SIZE bytes of file content read ahead of printing; a
+    /// bigger file is still read, on its own. Accepts k, M and G suffixes
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    read_buffer_cap: Option<u64>,
+
+    /// Stream files of at least SIZE bytes to the output a line at a time
+    /// instead of reading them into memory, unless an option needs the
+    /// whole file; invalid UTF-8 in them shows as `�`. Accepts k, M and G
+    /// suffixes
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "16M")]
+    stream_threshold: u64,
+
+    /// Skip files larger than SIZE bytes; accepts k, M and G suffixes
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_size: Option<u64>,
+
+    /// Skip files smaller than SIZE bytes; accepts k, M and G suffixes
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    min_size: Option<u64>,
+
+    /// Stop printing once the content printed so far reaches N lines in total
+    #[arg(long, value_name = "N")]
+    max_total_lines: Option<usize>,
+
+    /// With --max-total-lines, leave out a file that would cross the limit
+    /// instead of printing as much of it as fits
+    #[arg(long, requires = "max_total_lines")]
+    no_split_files: bool,
+
+    /// Print files in order until the next would take the content past an
+    /// estimated N tokens (about four characters each), then stop and report
+    /// what fit and what was left out
+    #[arg(long, value_name = "N")]
+    fill_to_tokens: Option<usize>,
+
+    /// Collapse runs of adjacent files with identical content into one print
+    /// whose header lists every alias
+    #[arg(long)]
+    merge_identical: bool,
+
+    /// After printing, list on stderr each group of files whose content is
+    /// identical; what's printed is unchanged
+    #[arg(long)]
+    show_dupes: bool,
+
+    /// Abort with an error if the whole run takes longer than SECONDS,
+    /// keeping whatever was printed (or written to --output) by then
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    timeout: Option<Duration>,
+
+    /// Number of threads used to expand inputs and read files (default: one
+    /// per CPU)
+    #[arg(long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+
+    /// Report to stderr how long glob expansion, directory walking, reading
+    /// and rendering took
+    #[arg(long)]
+    timing: bool,
+
+    /// Show a progress bar on stderr while files are read, with throughput
+    /// and an ETA from their total size (only when stderr is a terminal)
+    #[arg(long)]
+    progress: bool,
+
+    /// After printing, report to stderr how many files, lines and bytes were
+    /// printed, leaving out binary and size-limited files, along with how
+    /// many binary files were dropped silently (as with --quiet-binary) and
+    /// what --max-per-ext left out
+    #[arg(long)]
+    stats: bool,
+
+    /// Keep only the first file each input matches, by sorted path
+    #[arg(long)]
+    first: bool,
+
+    /// Only print files with a line matching this regular expression
+    #[arg(long, value_name = "PATTERN")]
+    grep: Option<String>,
+
+    /// Print only the definition of the Rust item NAME (a function, type,
+    /// trait, const, module or macro) from each file, under a
+    /// `path:START-END` header; files without one are left out
+    #[arg(long, value_name = "NAME")]
+    symbol: Option<String>,
+
+    /// With --grep, also write how many matches each printed file had to
+    /// stderr
+    #[arg(long, requires = "grep")]
+    match_summary: bool,
+
+    /// Check printed content for likely secrets (AWS access keys, private
+    /// keys, GitHub and Slack tokens, long random-looking strings) and warn
+    /// on stderr about the files and rules that matched. A safety net, not a
+    /// guarantee: anything else gets through
+    #[arg(long)]
+    scan_secrets: bool,
+
+    /// With --scan-secrets, stop with an error instead of printing a file
+    /// that looks like it holds a secret
+    #[arg(long, requires = "scan_secrets")]
+    fail_on_secret: bool,
+
+    /// Print just the paths of the files that would be printed, one per
+    /// line, without reading them unless a filter such as --grep needs to
+    #[arg(long, conflicts_with_all = ["as_patch", "index_footer"])]
+    list: bool,
+
+    /// With --list, end each path with a NUL byte instead of a newline, for
+    /// `xargs -0`
+    #[arg(short = '0', long, requires = "list")]
+    null: bool,
+
+    /// Print a JSON array of `{"path", "content", "bytes"}` objects instead;
+    /// binary files get `"content": null` and `"skipped": "binary"`.
+    /// Separators and header options don't apply
+    #[arg(long, conflicts_with_all = ["as_patch", "tree", "index_footer", "list"])]
+    json: bool,
+
+    /// Extra per-file fields for --json, comma-separated: `size` (bytes on
+    /// disk), `lines`, `mtime` (Unix seconds), `hash` (SHA-256 of the file)
+    /// and `lang`
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FIELDS",
+        value_delimiter = ',',
+        requires = "json"
+    )]
+    json_fields: Vec<JsonField>,
+
+    /// With --grep, print only the lines around each match
+    #[arg(long, requires = "grep")]
+    auto_range: bool,
+
+    /// Print only the lines changed since `HEAD` in each file (via `git diff`),
+    /// omitting unchanged files, and with a warning any outside a git work tree
+    #[arg(long, conflicts_with = "auto_range")]
+    changed: bool,
+
+    /// Prefix each line with its last commit's short hash and author from
+    /// `git blame` (slow; untracked files print unannotated)
+    #[arg(long)]
+    blame: bool,
+
+    /// Lines of context kept around each hunk by --auto-range and --changed
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    context_lines: usize,
+
+    /// Print only the first and last N/2 lines of longer files
+    #[arg(long, value_name = "N")]
+    truncate_middle: Option<usize>,
+
+    /// Print only the code and markdown cell sources of `.ipynb` files
+    #[arg(long)]
+    render_notebooks: bool,
+
+    /// What to do with a leading `---`-delimited YAML block in Markdown files
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value_t = Frontmatter::Keep,
+        conflicts_with_all = ["blame", "changed"]
+    )]
+    frontmatter: Frontmatter,
+
+    /// Re-indent `.json` files for readability
+    #[arg(long, conflicts_with_all = ["blame", "changed"])]
+    pretty_json: bool,
+
+    /// Draw `.csv` and `.tsv` files as aligned tables, cutting very long
+    /// cells and very wide tables short; files that don't parse are printed
+    /// as they are
+    #[arg(long, conflicts_with_all = ["blame", "changed"])]
+    render_tables: bool,
+
+    /// Most rows below the header --render-tables shows of each file
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 200,
+        requires = "render_tables"
+    )]
+    table_max_rows: usize,
+
+    /// Re-wrap prose paragraphs in `.md` files to N columns, leaving
+    /// headings, lists, code blocks, quotes and tables as they are
+    #[arg(long, value_name = "N", conflicts_with_all = ["blame", "changed"])]
+    reflow_md: Option<usize>,
+
+    /// Drop Rust doc comments (`///`, `//!`) and Python docstrings; a
+    /// line-based heuristic, so unusual layouts may be left alone
+    #[arg(long, conflicts_with_all = ["blame", "changed"])]
+    strip_docstrings: bool,
+
+    /// Drop `#[cfg(test)]` items, such as `mod tests`, from `.rs` files,
+    /// keeping the code under test; files that don't parse are printed whole
+    #[arg(long, conflicts_with_all = ["blame", "changed"])]
+    strip_test_modules: bool,
+
+    /// Replace the current directory's absolute path, wherever it starts a
+    /// path in printed content, with `./`
+    #[arg(long)]
+    rewrite_abs_paths: bool,
+
+    /// Prefix each line with its line number in the file, e.g. `  42 | ...`
+    #[arg(short = 'n', long)]
+    line_numbers: bool,
+
+    /// Render tabs as `→` and trailing spaces as `·` in printed content
+    #[arg(long)]
+    show_whitespace: bool,
+
+    /// Start every content line with STR (e.g. `> ` or four spaces) for
+    /// quoting; headers are left as they are
+    #[arg(long, value_name = "STR", allow_hyphen_values = true)]
+    line_prefix: Option<String>,
+
+    /// Append each file's octal permissions to its header (Unix only)
+    #[arg(long)]
+    show_mode: bool,
+
+    /// Print binary files too, decoded lossily with invalid UTF-8 shown as
+    /// `�`
+    #[arg(long, conflicts_with = "quiet_binary")]
+    include_binary: bool,
+
+    /// Read FIFOs, sockets and devices instead of skipping them, giving up
+    /// on each after a couple of seconds
+    #[arg(long)]
+    allow_special: bool,
+
+    /// Leave binary files out entirely instead of printing a
+    /// `[binary file skipped]` placeholder under their header
+    #[arg(long)]
+    quiet_binary: bool,
+
+    /// Print `(empty file)` under the header of files with no content
+    #[arg(long)]
+    mark_empty_files: bool,
+
+    /// Note a symlink's target in its header, e.g. `link.rs -> ../real.rs`
+    #[arg(long)]
+    show_symlinks: bool,
+
+    /// Replace each path in the headers with a placeholder such as
+    /// `file_01.rs`, keeping the extension, and write the mapping back to the
+    /// real paths to stderr (or --mapping-file). Content is printed as is
+    #[arg(
+        long,
+        conflicts_with_all = ["show_symlinks", "split_markers", "as_patch", "json", "tree", "tree_json"]
+    )]
+    anonymize_paths: bool,
+
+    /// Write the --anonymize-paths mapping to FILE instead of stderr
+    #[arg(long, value_name = "FILE", requires = "anonymize_paths")]
+    mapping_file: Option<PathBuf>,
+
+    /// Wrap each file's content in `<<<FILE: path>>>` and
+    /// `<<<END FILE: path>>>` lines so the bundle can be split back up
+    #[arg(long, conflicts_with = "merge_identical")]
+    split_markers: bool,
+
+    /// Print the files as a patch that recreates them with `git apply`
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "tree",
+            "index_footer",
+            "merge_identical",
+            "blame",
+            "line_prefix",
+            "header_format",
+            "markdown"
+        ]
+    )]
+    as_patch: bool,
+
+    /// Print a tree of the files before their contents; single-child
+    /// directory chains fold onto one line, e.g. `a/b/c/`. With --count or
+    /// --list, print just the tree
+    #[arg(long)]
+    tree: bool,
+
+    /// Instead of printing contents, print the layout of the files as nested
+    /// JSON: directories with sorted `children`, rooted like --tree
+    #[arg(long, conflicts_with_all = ["tree", "json", "count", "list"])]
+    tree_json: bool,
+
+    /// Give each file in --tree-json its size in bytes
+    #[arg(long, requires = "tree_json")]
+    tree_json_sizes: bool,
+
+    /// After the contents, print an index mapping each file to the 1-based
+    /// line range its content occupies in the output
+    #[arg(long)]
+    index_footer: bool,
+
+    /// Append a checksum of each file's printed content to its header
+    #[arg(long, value_enum, value_name = "ALGO")]
+    inline_checksum: Option<Checksum>,
+
+    /// Note the encoding each file was decoded from in its header, e.g.
+    /// `=== notes.txt (utf-16le) ===`; files without a byte-order mark are
+    /// `utf-8`
+    #[arg(long)]
+    show_encoding: bool,
+
+    /// Print a unified diff of exactly two files instead of their contents
+    #[arg(long)]
+    diff: bool,
+
+    /// Print a diff for each file that differs from its copy at the same
+    /// relative path under DIR, and list files added or removed
+    #[arg(long, value_name = "DIR", conflicts_with = "diff")]
+    diff_against: Option<PathBuf>,
+
+    /// When to colorize output; `auto` colors only a terminal and honors NO_COLOR
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Resolve the choice against the environment for stdout.
+    fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && std::io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Recreate the files in a bundle printed with --split-markers
+    Unbundle {
+        /// Bundle to read (default: stdin)
+        bundle: Option<PathBuf>,
+
+        /// Directory to recreate the files under
+        #[arg(short = 'C', long, value_name = "DIR", default_value = ".")]
+        dir: PathBuf,
+
+        /// Overwrite files that already exist
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// Optional fields `--json-fields` can add to each --json object.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum JsonField {
+    Size,
+    Lines,
+    Mtime,
+    Hash,
+    Lang,
+}
+
+/// `--sort` orders.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum SortMode {
+    /// By path
+    Path,
+    /// Most recently modified first
+    Mtime,
+    /// Largest first
+    Size,
+    /// As given
+    None,
+}
+
+/// `--frontmatter` handling for Markdown files.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Frontmatter {
+    /// Print it like the rest of the file
+    Keep,
+    /// Drop it, printing just the document
+    Strip,
+    /// Print only the frontmatter, delimiters included
+    Only,
+}
+
+/// `--on-unencodable` handling for characters outside --output-encoding.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Unencodable {
+    /// Write `?` in their place
+    Replace,
+    /// Write `?` in their place and say how many there were on stderr
+    Warn,
+}
+
+/// `--unauthored` handling for files without git history.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Unauthored {
+    /// Leave them out
+    Exclude,
+    /// Stop with an error
+    Error,
+}
+
+/// How `--group-precedence` resolves a path both included and excluded.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Precedence {
+    ExcludeWins,
+    IncludeWins,
+}
+
+/// Predefined extension sets for `--lang`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Lang {
+    /// rs, toml
+    Rust,
+    /// py, pyi
+    Python,
+    /// go, mod
+    Go,
+    /// c, h, cc, cpp, hpp
+    C,
+    /// html, css, js, ts
+    Web,
+}
+
+impl Lang {
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Lang::Rust => &["rs", "toml"],
+            Lang::Python => &["py", "pyi"],
+            Lang::Go => &["go", "mod"],
+            Lang::C => &["c", "h", "cc", "cpp", "hpp"],
+            Lang::Web => &["html", "css", "js", "ts"],
+        }
+    }
+}
+
+/// A resolved file, remembering the directory input that produced it.
+struct FileEntry {
+    path: PathBuf,
+    /// The directory argument this file was found under, if any.
+    root: Option<PathBuf>,
+    /// Named directly rather than found by a walk or glob, which exempts it
+    /// from the filters applied after collection (unless --strict-filters).
+    explicit: bool,
+    /// The lines to print, from a `path:START-END` input.
+    lines: Option<LineRange>,
+}
+
+/// A `--content-type-map` entry.
+#[derive(Clone, Debug)]
+struct ContentType {
+    ext: String,
+    lang: String,
+    /// Print only the content of these `<tag>` blocks.
+    section: Option<String>,
+}
+
+impl ContentType {
+    /// The entry for `path`'s extension, if any; later entries win.
+    fn lookup<'a>(cli: &'a Cli, path: &Path) -> Option<&'a ContentType> {
+        let ext = path.extension()?.to_str()?;
+        cli.content_type_map
+            .iter()
+            .rev()
+            .find(|t| t.ext.eq_ignore_ascii_case(ext))
+    }
+}
+
+fn parse_content_type(s: &str) -> Result<ContentType, String> {
+    let (ext, rest) = s
+        .split_once('=')
+        .ok_or_else(|| format!("`{s}` is not EXT=LANG[:TAG]"))?;
+    let (lang, section) = match rest.split_once(':') {
+        Some((lang, tag)) => (lang, Some(tag.to_string())),
+        None => (rest, None),
+    };
+    if ext.is_empty() || lang.is_empty() || section.as_deref() == Some("") {
+        return Err(format!("`{s}` is not EXT=LANG[:TAG]"));
+    }
+    Ok(ContentType {
+        ext: ext.trim_start_matches('.').to_string(),
+        lang: lang.to_string(),
+        section,
+    })
+}
+
+/// A unit of output: one file's content, printed under one or more names.
+struct Document {
+    files: Vec<FileEntry>,
+    content: String,
+    /// Stands in for a binary file, with the placeholder as its content.
+    binary: bool,
+    /// What the content was decoded from, for --show-encoding; `None` when
+    /// there is no content to speak of.
+    encoding: Option<&'static Encoding>,
+    /// A word for the header, like --state-file's `new`.
+    note: Option<&'static str>,
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    if let Some(limit) = cli.timeout {
+        spawn_watchdog(limit);
+    }
+    let start = Instant::now();
+    let result = dispatch(&cli);
+    if cli.timing {
+        timing::report(start.elapsed());
+    }
+    result
+}
+
+/// Run whichever mode the command line asks for.
+fn dispatch(cli: &Cli) -> anyhow::Result<()> {
+    if let Some(Command::Unbundle { bundle, dir, force }) = &cli.command {
+        return unbundle::run(bundle.as_deref(), dir, *force);
+    }
+    if cli.diff {
+        return run_diff(cli);
+    }
+    if let Some(lists_dir) = &cli.lists_dir {
+        return run_lists(cli, lists_dir);
+    }
+
+    let mut inputs = cli.inputs.clone();
+    if let Some(list) = &cli.files_from {
+        inputs.extend(read_list(list)?);
+    }
+    if let Some(dir) = &cli.diff_against {
+        return run_diff_against(cli, &inputs, dir);
+    }
+    if cli.check_utf8 {
+        return run_check_utf8(cli, &inputs);
+    }
+    if cli.count && !cli.tree {
+        return run_count(cli, &inputs);
+    }
+    if cli.ext_stats && !cli.stats {
+        return run_ext_stats(cli, &inputs);
+    }
+    if cli.tree_json {
+        return run_tree_json(cli, &inputs);
+    }
+    if cli.split_by_dir {
+        return run_split(cli, &inputs);
+    }
+    if cli.clipboard {
+        return copy_to_clipboard(cli, &inputs);
+    }
+    if cli.edit {
+        return open_in_editor(cli, &inputs);
+    }
+    if cli.watch {
+        return watch::run(cli.output.as_deref(), |set| {
+            let files = resolve(cli, &inputs)?;
+            set.update(watched_dirs(&inputs, &files))?;
+            write_bundle(cli, files)
+        });
+    }
+    write_bundle(cli, resolve(cli, &inputs)?)
+}
+
+/// Print `files` to stdout or --output, in the --output-encoding.
+fn write_bundle(cli: &Cli, files: Vec<FileEntry>) -> anyhow::Result<()> {
+    match (&cli.output, cli.output_encoding) {
+        (None, None) => return print_files(cli, files, Box::new(io::stdout())),
+        // Written as it's printed, so a --timeout leaves what was done.
+        (Some(path), None) => {
+            // The file is emptied before anything is read, so it can't
+            // usefully be one of the inputs.
+            let target = canonical_path(path);
+            let files = files
+                .into_iter()
+                .filter(|f| target.is_none() || canonical_path(&f.path) != target)
+                .collect();
+            let file = fs::File::create(path)
+                .map_err(|e| anyhow::anyhow!("cannot write `{}`: {e}", path.display()))?;
+            return print_files(cli, files, Box::new(file));
+        }
+        _ => {}
+    }
+    let mut buf = Vec::new();
+    print_files(cli, files, Box::new(&mut buf))?;
+    let bytes = encode_output(cli, buf);
+    match &cli.output {
+        Some(path) => fs::write(path, bytes)
+            .map_err(|e| anyhow::anyhow!("cannot write `{}`: {e}", path.display())),
+        None => Ok(io::stdout().write_all(&bytes)?),
+    }
+}
+
+/// The directories --watch keeps an eye on: those holding `files`, and any
+/// directories among `inputs`, canonicalized so they compare with the
+/// paths in change events.
+fn watched_dirs(inputs: &[String], files: &[FileEntry]) -> Vec<PathBuf> {
+    let parents = files.iter().map(|f| match f.path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    });
+    let dirs = inputs.iter().map(PathBuf::from).filter(|p| p.is_dir());
+    parents
+        .chain(dirs)
+        .filter_map(|d| fs::canonicalize(d).ok())
+        .collect()
+}
+
+/// `--clipboard`: bundle `inputs` onto the system clipboard, and to stdout
+/// too with --tee.
+fn copy_to_clipboard(cli: &Cli, inputs: &[String]) -> anyhow::Result<()> {
+    let files = resolve(cli, inputs)?;
+    let count = files.len();
+    let mut buf = Vec::new();
+    print_files(cli, files, Box::new(&mut buf))?;
+    if cli.tee {
+        io::stdout().write_all(&buf)?;
+    }
+    let text = String::from_utf8_lossy(&buf).into_owned();
+    let mut clipboard =
+        arboard::Clipboard::new().map_err(|e| anyhow::anyhow!("cannot open the clipboard: {e}"))?;
+    clipboard
+        .set_text(text)
+        .map_err(|e| anyhow::anyhow!("cannot copy to the clipboard: {e}"))?;
+    let noun = if count == 1 { "file" } else { "files" };
+    eprintln!(
+        "copied {count} {noun} ({}) to clipboard",
+        format_size(buf.len() as u64)
+    );
+    Ok(())
+}
+
+/// `--edit`: bundle `inputs` into the editor, and to stdout too with
+/// --tee; just print it when there's no terminal to edit in.
+fn open_in_editor(cli: &Cli, inputs: &[String]) -> anyhow::Result<()> {
+    if !edit::interactive() {
+        eprintln!("warning: --edit needs a terminal; printing the bundle instead");
+        return write_bundle(cli, resolve(cli, inputs)?);
+    }
+    let files = resolve(cli, inputs)?;
+    let mut buf = Vec::new();
+    print_files(cli, files, Box::new(&mut buf))?;
+    if cli.tee {
+        io::stdout().write_all(&buf)?;
+        io::stdout().flush()?;
+    }
+    let ext = match () {
+        _ if cli.json => "json",
+        _ if cli.markdown => "md",
+        _ => "txt",
+    };
+    edit::open(&buf, ext)
+}
+
+/// `buf`, a rendered bundle, in the --output-encoding (if any).
+fn encode_output(cli: &Cli, buf: Vec<u8>) -> Vec<u8> {
+    let Some(encoding) = cli.output_encoding else {
+        return buf;
+    };
+    let text = String::from_utf8_lossy(&buf);
+    let (bytes, replaced) = encode::encode(&text, encoding);
+    if replaced > 0 && cli.on_unencodable == Unencodable::Warn {
+        eprintln!(
+            "warning: {replaced} character(s) not representable in {} were written as `?`",
+            encoding.name()
+        );
+    }
+    bytes
+}
+
+/// `--count`: a table of each file's size and estimated tokens, largest
+/// first, with a total. Binary files are left out.
+fn run_count(cli: &Cli, inputs: &[String]) -> anyhow::Result<()> {
+    struct Row {
+        name: String,
+        bytes: usize,
+        lines: usize,
+        tokens: usize,
+    }
+    let cwd = std::env::current_dir()?;
+    let base = display_base(cli, &cwd);
+    let mut rows = Vec::new();
+    for file in resolve(cli, inputs)? {
+        let Some(mut text) = read_file(&file.path)? else {
+            continue;
+        };
+        if let Some(range) = file.lines {
+            let span = range.span();
+            text = text
+                .split_inclusive('\n')
+                .skip(span.start)
+                .take(span.len())
+                .collect();
+        }
+        rows.push(Row {
+            name: header_name(&file, cli, &cwd, &base),
+            bytes: text.len(),
+            lines: text.lines().count(),
+            tokens: tokens::estimate(&text),
+        });
+    }
+    // Stable, so equal counts stay in path order.
+    rows.sort_by_key(|r| std::cmp::Reverse(r.tokens));
+    let total = Row {
+        name: "TOTAL".to_string(),
+        bytes: rows.iter().map(|r| r.bytes).sum(),
+        lines: rows.iter().map(|r| r.lines).sum(),
+        tokens: rows.iter().map(|r| r.tokens).sum(),
+    };
+
+    let width = rows
+        .iter()
+        .chain([&total])
+        .map(|r| r.name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("PATH".len());
+    let mut out = io::stdout();
+    writeln!(
+        out,
+        "{:<width$}  {:>10}  {:>8}  {:>8}",
+        "PATH", "BYTES", "LINES", "TOKENS"
+    )?;
+    for row in rows.iter().chain([&total]) {
+        writeln!(
+            out,
+            "{:<width$}  {:>10}  {:>8}  {:>8}",
+            row.name, row.bytes, row.lines, row.tokens
+        )?;
+    }
+    Ok(())
+}
+
+/// `--ext-stats`: per-extension file counts, bytes and lines, largest
+/// first, with a total. Binary files count towards files and bytes only.
+fn run_ext_stats(cli: &Cli, inputs: &[String]) -> anyhow::Result<()> {
+    let mut stats = ExtStats::default();
+    for file in resolve(cli, inputs)? {
+        let lines = read_file(&file.path)?.map_or(0, |text| text.lines().count());
+        stats.add(&file.path, fs::metadata(&file.path)?.len(), lines);
+    }
+    print!("{}", stats.render());
+    Ok(())
+}
+
+/// `--tree-json`: the layout of the resolved files as nested JSON, with no
+/// contents.
+fn run_tree_json(cli: &Cli, inputs: &[String]) -> anyhow::Result<()> {
+    let files = resolve(cli, inputs)?;
+    let cwd = std::env::current_dir()?;
+    let base = display_base(cli, &cwd);
+    let mut printer = Printer::new(cli, cwd, base, Box::new(io::stdout()));
+    printer.tree_json(&files)?;
+    Ok(printer.finish()?)
+}
+
+/// Report every resolved file that isn't valid UTF-8, failing if any aren't.
+fn run_check_utf8(cli: &Cli, inputs: &[String]) -> anyhow::Result<()> {
+    let files = resolve(cli, inputs)?;
+    let cwd = std::env::current_dir()?;
+    let base = display_base(cli, &cwd);
+    let mut invalid = 0;
+    for file in &files {
+        let bytes = fs::read(&file.path)?;
+        if let Err(e) = std::str::from_utf8(&bytes) {
+            println!(
+                "{}: invalid UTF-8 at byte {}",
+                header_name(file, cli, &cwd, &base),
+                e.valid_up_to()
+            );
+            invalid += 1;
+        }
+    }
+    if invalid > 0 {
+        anyhow::bail!("{invalid} of {} files are not valid UTF-8", files.len());
+    }
+    Ok(())
+}
+
+/// Write one bundle per top-level directory of the resolved files into
+/// --output-dir, with files at the top level going to `root.txt`.
+fn run_split(cli: &Cli, inputs: &[String]) -> anyhow::Result<()> {
+    let out_dir = cli
+        .output_dir
+        .as_deref()
+        .expect("clap requires --output-dir with --split-by-dir");
+    let cwd = std::env::current_dir()?;
+    let mut groups: BTreeMap<String, Vec<FileEntry>> = BTreeMap::new();
+    for file in resolve(cli, inputs)? {
+        let path = normalize(&file.path);
+        let rel = path.strip_prefix(&cwd).unwrap_or(&path);
+        let mut parts = rel.components();
+        let group = match (parts.next(), parts.next()) {
+            (Some(top), Some(_)) => top.as_os_str().to_string_lossy().into_owned(),
+            _ => "root".to_string(),
+        };
+        groups.entry(group).or_default().push(file);
+    }
+
+    fs::create_dir_all(out_dir)?;
+    for (group, files) in groups {
+        let mut buf = Vec::new();
+        print_files(cli, files, Box::new(&mut buf))?;
+        fs::write(
+            out_dir.join(format!("{group}.txt")),
+            encode_output(cli, buf),
+        )?;
+    }
+    Ok(())
+}
+
+/// Resolve `inputs` and print every file they name to `out`.
+fn bundle(cli: &Cli, inputs: &[String], out: Box<dyn Write + '_>) -> anyhow::Result<()> {
+    let files = resolve(cli, inputs)?;
+    print_files(cli, files, out)
+}
+
+/// Expand `inputs` into the final, filtered list of files to print.
+fn resolve(cli: &Cli, inputs: &[String]) -> anyhow::Result<Vec<FileEntry>> {
+    // Inputs expand independently, so walk them in parallel; collecting
+    // keeps input order, and the first failing input reports its error.
+    let pool = thread_pool(cli)?;
+    // `~`, `$VAR`, brace and then group expansion come first, so what they
+    // produce is globbed like any other input. Brace and group expansion
+    // share one --max-expansions budget per input.
+    let mut expanded_inputs = Vec::with_capacity(inputs.len());
+    for raw in inputs {
+        let raw = match raw.starts_with('~') || raw.contains('$') {
+            true => shell::expand(raw)?,
+            false => raw.clone(),
+        };
+        let alternatives = match raw.contains('{') {
+            true => braces::expand(&raw, cli.max_expansions)?,
+            false => vec![raw.clone()],
+        };
+        let mut limit = ExpansionLimit {
+            left: cli.max_expansions,
+            max: cli.max_expansions,
+            input: &raw,
+        };
+        for alt in alternatives {
+            let (pattern, lines) = LineRange::split(&alt)?;
+            let group = match pattern.contains('(') {
+                true => expand_group_pattern(pattern, cli.group_precedence, &limit)?,
+                false => GroupExpansion {
+                    includes: vec![pattern.to_string()],
+                    excludes: Vec::new(),
+                },
+            };
+            limit.take(group.includes.len() + group.excludes.len())?;
+            expanded_inputs.push((group, lines));
+        }
+    }
+    let inputs = expanded_inputs;
+    let patterns = || inputs.iter().flat_map(|(group, _)| &group.includes);
+    for raw in patterns().filter(|raw| is_sweeping_glob(raw)) {
+        eprintln!(
+            "warning: `{raw}` matches every file under the current directory, which can \
+             take a long time; consider a more specific pattern such as `src/**/*.rs`"
+        );
+        if cli.interactive && !cli.yes {
+            anyhow::bail!("refusing to expand `{raw}` in --interactive mode without --yes");
+        }
+    }
+    // `-` reads plain paths from stdin, once, however often it's given.
+    let stdin_paths = if patterns().any(|raw| raw == "-") {
+        parse_list(&io::read_to_string(io::stdin())?)
+    } else {
+        Vec::new()
+    };
+    let expanded: Vec<anyhow::Result<Vec<FileEntry>>> = pool.install(|| {
+        inputs
+            .par_iter()
+            .map(|(group, lines)| match group.includes.as_slice() {
+                [stdin] if stdin == "-" => {
+                    stdin_paths.iter().try_fold(Vec::new(), |mut acc, path| {
+                        acc.extend(collect_path(Path::new(path), cli)?);
+                        anyhow::Ok(acc)
+                    })
+                }
+                _ => {
+                    let mut found = collect_input(group, cli)?;
+                    for file in &mut found {
+                        file.lines = *lines;
+                    }
+                    Ok(found)
+                }
+            })
+            .collect()
+    });
+    let mut files: Vec<FileEntry> = Vec::new();
+    for found in expanded {
+        let mut found = found?;
+        if cli.first {
+            found.sort_by(|a, b| a.path.cmp(&b.path));
+            found.truncate(1);
+        }
+        files.extend(found);
+    }
+
+    if !cli.exclude.is_empty() {
+        let cwd = std::env::current_dir()?;
+        let relative = glob_matcher(".", &cli.exclude)?;
+        let absolute = glob_matcher("/", &cli.exclude)?;
+        files.retain(|f| {
+            let path = normalize(&f.path);
+            f.explicit
+                || !(relative.matched(&path, false).is_whitelist()
+                    || absolute
+                        .matched(normalize(&cwd.join(&path)), false)
+                        .is_whitelist())
+        });
+    }
+    if let Some(ignore) = fprignore(cli)? {
+        let cwd = std::env::current_dir()?;
+        files.retain(|f| {
+            let path = normalize(&cwd.join(&f.path));
+            f.explicit
+                || !path.starts_with(ignore.path())
+                || !ignore.matched_path_or_any_parents(&path, false).is_ignore()
+        });
+    }
+
+    let sort = match cli.preserve_order {
+        true => SortMode::None,
+        false => cli.sort,
+    };
+    if sort != SortMode::None {
+        // Stable sort, so the dedup keeps the first-seen entry (and its root).
+        files.sort_by(|a, b| (&a.path, a.lines).cmp(&(&b.path, b.lines)));
+    }
+    // Keep the first occurrence of each file (and range), however it was
+    // named: `src/main.rs`, `./src/../src/main.rs` and a symlink to it are
+    // the same file. Naming a file explicitly wins over reaching it by a
+    // walk, so it's shown the way it was named.
+    let mut first: HashMap<(PathBuf, Option<LineRange>), usize> = HashMap::new();
+    let mut kept: Vec<FileEntry> = Vec::with_capacity(files.len());
+    for file in files {
+        let key = (dedup_key(&file.path), file.lines);
+        match first.get(&key) {
+            Some(&i) if file.explicit && !kept[i].explicit => kept[i] = file,
+            Some(_) => {}
+            None => {
+                first.insert(key, kept.len());
+                kept.push(file);
+            }
+        }
+    }
+    files = kept;
+    match sort {
+        SortMode::Path if cli.sort_dirs_before_files => {
+            files.sort_by(|a, b| tree::dirs_first(&a.path, &b.path));
+        }
+        // Stable again, so ties stay in path order.
+        SortMode::Mtime => files.sort_by_cached_key(|f| {
+            std::cmp::Reverse(fs::metadata(&f.path).and_then(|m| m.modified()).ok())
+        }),
+        SortMode::Size => files.sort_by_cached_key(|f| {
+            std::cmp::Reverse(fs::metadata(&f.path).map_or(0, |m| m.len()))
+        }),
+        _ => {}
+    }
+    if cli.reverse {
+        files.reverse();
+    }
+
+    if !cli.lang.is_empty() {
+        let exts: Vec<&str> = cli
+            .lang
+            .iter()
+            .flat_map(|l| l.extensions())
+            .copied()
+            .collect();
+        files.retain(|f| f.explicit || has_extension(&f.path, &exts));
+    }
+    if !cli.ext.is_empty() {
+        let exts: Vec<&str> = cli.ext.iter().map(|e| e.trim_start_matches('.')).collect();
+        files.retain(|f| f.explicit || has_extension(&f.path, &exts));
+    }
+    if cli.skip_lockfiles {
+        files.retain(|f| {
+            let name = f.path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            f.explicit || !LOCKFILES.contains(&name) && !cli.lockfile.iter().any(|l| l == name)
+        });
+    }
+    if cli.exclude_tests {
+        let cwd = std::env::current_dir()?;
+        files.retain(|f| f.explicit || !testcode::is_test_file(&f.path, &cwd, f.root.as_deref()));
+    }
+
+    if cli.tracked_only {
+        let cwd = std::env::current_dir()?;
+        if !git::in_work_tree(&cwd) {
+            anyhow::bail!("--tracked-only requires running inside a git repository");
+        }
+        let tracked = git::tracked_files(&cwd, cli.submodules)?;
+        files.retain(|f| {
+            f.explicit || canonical_path(&f.path).is_some_and(|p| tracked.contains(&p))
+        });
+    }
+    if let Some((name, wanted)) = cli
+        .authored_by
+        .as_ref()
+        .map(|n| (n, true))
+        .or(cli.not_authored_by.as_ref().map(|n| (n, false)))
+    {
+        let mut kept = Vec::with_capacity(files.len());
+        for f in files {
+            if f.explicit {
+                kept.push(f);
+                continue;
+            }
+            match git::last_author(&f.path) {
+                Some(author) if (&author == name) == wanted => kept.push(f),
+                Some(_) => {}
+                None if cli.unauthored == Unauthored::Error => {
+                    anyhow::bail!(
+                        "{} has no git history to take an author from",
+                        f.path.display()
+                    )
+                }
+                None => {}
+            }
+        }
+        files = kept;
+    }
+    if let Some(name) = &cli.symbol {
+        files = symbol_definitions(files, name);
+    }
+    if let Some(max) = cli.max_per_ext {
+        files = cap_per_extension(files, max, cli.stats);
+    }
+    if cli.interactive {
+        files = select::choose(files, &std::env::current_dir()?)?;
+    }
+    Ok(files)
+}
+
+/// The first `max` of `files` with each extension, in order, reporting
+/// how many of each were left out when `report`ing for --stats.
+fn cap_per_extension(files: Vec<FileEntry>, max: usize, report: bool) -> Vec<FileEntry> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut kept = Vec::with_capacity(files.len());
+    for file in files {
+        let ext = file
+            .path
+            .extension()
+            .map_or(String::new(), |e| e.to_string_lossy().to_lowercase());
+        let count = seen.entry(ext).or_default();
+        *count += 1;
+        if *count <= max {
+            kept.push(file);
+        }
+    }
+    if report {
+        let mut trimmed: Vec<(String, usize)> = seen
+            .into_iter()
+            .filter(|(_, count)| *count > max)
+            .map(|(ext, count)| (ext, count - max))
+            .collect();
+        trimmed.sort();
+        for (ext, n) in trimmed {
+            let noun = if n == 1 { "file" } else { "files" };
+            match ext.as_str() {
+                "" => eprintln!("--max-per-ext left out {n} {noun} without an extension"),
+                _ => eprintln!("--max-per-ext left out {n} .{ext} {noun}"),
+            }
+        }
+    }
+    kept
+}
+
+/// One entry per definition of `name` across `files`, with its lines
+/// selected. Files that aren't Rust are skipped with a warning, as are files
+/// that can't be read or parsed.
+fn symbol_definitions(files: Vec<FileEntry>, name: &str) -> Vec<FileEntry> {
+    let mut found = Vec::new();
+    for file in files {
+        let path = &file.path;
+        if !has_extension(path, &["rs"]) {
+            eprintln!(
+                "warning: --symbol only understands Rust; skipping {}",
+                path.display()
+            );
+            continue;
+        }
+        let text = match read_file(path) {
+            Ok(Some(text)) => text,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("warning: cannot read {}: {e}", path.display());
+                continue;
+            }
+        };
+        let ranges = match symbol::find_rust(&text, name) {
+            Ok(ranges) => ranges,
+            Err(e) => {
+                eprintln!("warning: cannot parse {}: {e}", path.display());
+                continue;
+            }
+        };
+        // A `path:START-END` input only searches within its lines.
+        let inside = |r: &LineRange| {
+            file.lines
+                .is_none_or(|outer| outer.span().contains(&(r.start - 1)))
+        };
+        for lines in ranges.into_iter().filter(inside) {
+            found.push(FileEntry {
+                path: file.path.clone(),
+                root: file.root.clone(),
+                explicit: file.explicit,
+                lines: Some(lines),
+            });
+        }
+    }
+    found
+}
+
+/// What displayed paths are relative to: the --smart-root project root,
+/// or else `cwd`.
+fn display_base(cli: &Cli, cwd: &Path) -> PathBuf {
+    match cli.smart_root {
+        true => find_project_root(cwd, &cli.root_markers).unwrap_or_else(|| cwd.to_path_buf()),
+        false => cwd.to_path_buf(),
+    }
+}
+
+/// Can big files be streamed under these options? Only if every transform
+/// asked for works a line at a time and nothing needs the whole content.
+fn streamable(cli: &Cli) -> bool {
+    !(cli.grep.is_some()
+        || cli.changed
+        || cli.blame
+        || cli.merge_identical
+        || cli.seen_cache.is_some()
+        || cli.max_total_lines.is_some()
+        || cli.fill_to_tokens.is_some()
+        || cli.truncate_middle.is_some()
+        || cli.skip_minified
+        || cli.strip_docstrings
+        || cli.strip_test_modules
+        || cli.pretty_json
+        || cli.render_tables
+        || cli.numbered_separators
+        || cli.reflow_md.is_some()
+        || cli.scan_secrets
+        || cli.show_dupes
+        || cli.state_file.is_some()
+        || cli.render_notebooks
+        || cli.frontmatter != Frontmatter::Keep
+        || !cli.content_type_map.is_empty()
+        || cli.inline_checksum.is_some()
+        || cli.markdown
+        || cli.split_markers
+        || cli.index_footer
+        || cli.single
+        || cli.json
+        || cli.as_patch)
+}
+
+/// Print `file` by streaming it from disk a line at a time, applying the
+/// line-by-line transforms on the way. Returns `false`, having printed
+/// nothing, if the file's start says it must be read whole after all.
+fn stream_file(
+    printer: &mut Printer,
+    file: &FileEntry,
+    cli: &Cli,
+    abs_prefix: &str,
+) -> anyhow::Result<bool> {
+    let path = &file.path;
+    let Some(mut reader) = read::open_streamable(path)? else {
+        return Ok(false);
+    };
+    let span = file.lines.map_or(0..usize::MAX, LineRange::span);
+    // Numbers are right-aligned to the last one, so count the lines first.
+    let width = match cli.line_numbers {
+        true => count_lines(path)?
+            .min(span.end)
+            .max(span.start + 1)
+            .to_string()
+            .len(),
+        false => 0,
+    };
+    timing::time(Phase::Render, || {
+        printer.print_streamed(file, |out| {
+            let mut buf = Vec::new();
+            let mut bytes = 0;
+            // Blank lines wait for something after them, since trailing ones
+            // are dropped; the content ends in exactly one newline.
+            let mut blanks: Vec<String> = Vec::new();
+            let mut mid_line = false;
+            for i in 0..span.end {
+                buf.clear();
+                if io::BufRead::read_until(&mut reader, b'\n', &mut buf)? == 0 {
+                    break;
+                }
+                if i < span.start {
+                    continue;
+                }
+                let mut line = String::from_utf8_lossy(&buf).into_owned();
+                if cli.line_numbers {
+                    line = transform::number_line(&line, i + 1, width);
+                }
+                if cli.rewrite_abs_paths {
+                    line = line.replace(abs_prefix, "./");
+                }
+                if cli.show_whitespace {
+                    line = transform::show_whitespace(&line);
+                }
+                if let Some(prefix) = &cli.line_prefix {
+                    line = transform::prefix_each(&line, prefix);
+                }
+                if line == "\n" || line == "\r\n" {
+                    blanks.push(line);
+                    continue;
+                }
+                for line in blanks.drain(..).chain([line]) {
+                    out.write_all(line.as_bytes())?;
+                    bytes += line.len();
+                    mid_line = !line.ends_with('\n');
+                }
+            }
+            if bytes == 0 {
+                // Nothing but blank lines still prints one.
+                blanks.truncate(1);
+                for line in blanks {
+                    out.write_all(line.as_bytes())?;
+                    bytes += line.len();
+                }
+            } else if mid_line {
+                out.write_all(b"\n")?;
+                bytes += 1;
+            }
+            Ok(bytes)
+        })
+    })?;
+    Ok(true)
+}
+
+/// How many lines `path` has, counting a last one without a newline.
+fn count_lines(path: &Path) -> io::Result<usize> {
+    let mut reader = io::BufReader::new(fs::File::open(path)?);
+    let (mut lines, mut mid_line) = (0, false);
+    loop {
+        let buf = io::BufRead::fill_buf(&mut reader)?;
+        if buf.is_empty() {
+            break;
+        }
+        lines += buf.iter().filter(|&&b| b == b'\n').count();
+        mid_line = buf.last() != Some(&b'\n');
+        let len = buf.len();
+        io::BufRead::consume(&mut reader, len);
+    }
+    Ok(lines + usize::from(mid_line))
 }
 
-fn main() {
-    if let Err(e) = run() {
-        eprintln!("{e}");
-        std::process::exit(1);
+/// `path`'s text, or `None` for a binary file unless --include-binary.
+fn load(path: &Path, cli: &Cli) -> anyhow::Result<Option<(String, &'static Encoding)>> {
+    match read_decoded(path)? {
+        None if cli.include_binary => Ok(Some((read_lossy(path)?, encoding_rs::UTF_8))),
+        text => Ok(text),
     }
 }
 
-fn run() -> anyhow::Result<()> {
-    let cli = Cli::parse();
-    let mut files: Vec<PathBuf> = Vec::new();
+/// The pool parallel work runs on, sized by --jobs.
+fn thread_pool(cli: &Cli) -> anyhow::Result<rayon::ThreadPool> {
+    Ok(rayon::ThreadPoolBuilder::new()
+        .num_threads(cli.jobs.map_or(0, NonZeroUsize::get))
+        .build()?)
+}
 
-    for raw in &cli.inputs {
-        // 1. Expand custom grouping syntax first.
-        let patterns = if raw.contains('(') {
-            expand_group_pattern(raw)?
-        } else {
-            vec![raw.clone()]
+/// Print `files` to `out` with every requested transform applied.
+fn print_files(cli: &Cli, files: Vec<FileEntry>, out: Box<dyn Write + '_>) -> anyhow::Result<()> {
+    let grep = cli
+        .grep
+        .as_deref()
+        .map(Regex::new)
+        .transpose()
+        .map_err(|e| anyhow::anyhow!("invalid --grep pattern: {e}"))?;
+
+    let cwd = std::env::current_dir()?;
+    let abs_prefix = format!("{}{}", cwd.display(), std::path::MAIN_SEPARATOR);
+    if cli.blame && !git::in_work_tree(&cwd) {
+        anyhow::bail!("--blame requires running inside a git repository");
+    }
+    let mut seen = match &cli.seen_cache {
+        Some(path) => Some(SeenCache::load(path, cli.reset_cache)?),
+        None => None,
+    };
+    let mut state = match &cli.state_file {
+        Some(path) => Some(RunState::load(path)?),
+        None => None,
+    };
+    let abs_path = |path: &Path| normalize(&cwd.join(path));
+    let removed = match state.as_mut() {
+        Some(state) => {
+            state.take_removed(&files.iter().map(|f| (abs_path(&f.path), f.lines)).collect())
+        }
+        None => Vec::new(),
+    };
+    let base = display_base(cli, &cwd);
+    let mut printer = Printer::new(cli, cwd.clone(), base, out);
+    // --numbered-separators can only count the documents once every filter
+    // has had its say, so they're held back until the end.
+    let mut held: Option<Vec<Document>> = cli.numbered_separators.then(Vec::new);
+    if cli.pad_headers {
+        printer.pad_headers(&files);
+    }
+    for (path, lines) in removed {
+        let doc = Document {
+            files: vec![FileEntry {
+                path,
+                root: None,
+                explicit: false,
+                lines,
+            }],
+            content: String::new(),
+            binary: false,
+            encoding: None,
+            note: Some("removed"),
         };
+        emit(&mut printer, &mut held, doc)?;
+    }
+    if cli.tree {
+        timing::time(Phase::Render, || printer.tree(&files))?;
+        if cli.count || cli.list {
+            return Ok(printer.finish()?);
+        }
+    }
+    let mut pending: Option<Document> = None;
 
-        // 2. Handle each resulting pattern as before.
-        for pat in patterns {
-            if is_glob(&pat) {
-                expand_glob(&pat, &mut files)?;
-            } else {
-                let path = PathBuf::from(&pat);
-                if path.is_dir() {
-                    expand_dir(&path, cli.recursive, &mut files)?;
-                } else if path.is_file() {
-                    files.push(path);
-                } else {
-                    anyhow::bail!("Input `{}` does not exist", pat);
+    let mut total_lines = 0;
+    let mut fill = TokenFill::default();
+    let mut matches: Vec<(String, usize)> = Vec::new();
+    let scanner = cli.scan_secrets.then(secrets::Scanner::new);
+    let mut secrets_found: Vec<(String, Vec<&str>)> = Vec::new();
+    // Files by content hash, keeping the order each hash was first seen in.
+    let mut by_hash: HashMap<String, Vec<String>> = HashMap::new();
+    let mut hashes: Vec<String> = Vec::new();
+    // Size limits are checked first, in order, so their notes come out in
+    // order too. Reads then run in parallel, and the loop takes the results
+    // in file order as they arrive, so the output matches reading one file
+    // at a time and a stalled read only holds back what comes after it.
+    let mut allowed = Vec::with_capacity(files.len());
+    for file in &files {
+        // Walked files were already checked; this catches named ones.
+        let path = &file.path;
+        let sized = !has_size_limit(cli) || size_allowed(path, fs::metadata(path)?.len(), cli);
+        allowed.push(sized && lines_allowed(path, cli)?);
+    }
+    // --list needs no content unless a filter looks at it.
+    let content_filtered = grep.is_some()
+        || cli.changed
+        || cli.quiet_binary
+        || cli.skip_minified
+        || cli.seen_cache.is_some()
+        || cli.fill_to_tokens.is_some()
+        || cli.max_total_lines.is_some()
+        || cli.show_dupes
+        || cli.state_file.is_some();
+    if cli.list && !content_filtered {
+        for (file, allowed) in files.into_iter().zip(allowed) {
+            if allowed {
+                printer.print(&Document {
+                    files: vec![file],
+                    content: String::new(),
+                    binary: false,
+                    encoding: None,
+                    note: None,
+                })?;
+            }
+        }
+        return Ok(printer.finish()?);
+    }
+    let total = files
+        .iter()
+        .zip(&allowed)
+        .filter(|(_, allowed)| **allowed)
+        .map(|(f, _)| {
+            fs::metadata(&f.path)
+                .ok()
+                .filter(|m| m.is_file())
+                .map(|m| m.len())
+        })
+        .sum();
+    let bar = progress::read_bar(cli.progress, total);
+    let can_stream = streamable(cli);
+    let streamed: Vec<bool> = files
+        .iter()
+        .zip(&allowed)
+        .map(|(f, &allowed)| {
+            allowed
+                && can_stream
+                && fs::metadata(&f.path)
+                    .is_ok_and(|m| m.is_file() && m.len() >= cli.stream_threshold)
+        })
+        .collect();
+    let reads: Vec<bool> = allowed
+        .iter()
+        .zip(&streamed)
+        .map(|(&a, &s)| a && !s)
+        .collect();
+    let paths: Vec<PathBuf> = files.iter().map(|f| f.path.clone()).collect();
+    let budget = ReadBudget::new(cli.read_buffer_cap);
+    thread_pool(cli)?.in_place_scope(|scope| {
+        std::thread::scope(|threads| {
+            let budget = &budget;
+            let _close = CloseOnDrop(budget);
+            let (tx, rx) = mpsc::channel();
+            // Reads are queued in order from a thread of their own, which
+            // waits while --read-buffer-cap bytes are read but not printed.
+            threads.spawn(move || {
+                // Streamed files are left for the printing loop to read.
+                for (i, (path, allowed)) in paths.into_iter().zip(reads).enumerate() {
+                    let len = match allowed {
+                        true => fs::metadata(&path).map_or(0, |m| m.len()),
+                        false => 0,
+                    };
+                    if !budget.acquire(len) {
+                        break;
+                    }
+                    let tx = tx.clone();
+                    scope.spawn(move |_| {
+                        let result =
+                            allowed.then(|| timing::time(Phase::Read, || load(&path, cli)));
+                        // The receiver only goes away once printing has stopped.
+                        let _ = tx.send((i, (len, result)));
+                    });
+                }
+            });
+            let mut reads = InOrder::new(rx);
+            let mut streamed = streamed.into_iter();
+            let mut files = files.into_iter();
+            while let Some(file) = files.next() {
+                let path = &file.path;
+                let (len, mut result) = reads.next().expect("one result per file");
+                budget.release(len);
+                bar.inc(len);
+                if streamed.next() == Some(true) {
+                    if let Some(doc) = pending.take() {
+                        emit(&mut printer, &mut held, doc)?;
+                    }
+                    if stream_file(&mut printer, &file, cli, &abs_prefix)? {
+                        bar.inc(fs::metadata(path).map_or(0, |m| m.len()));
+                        continue;
+                    }
+                    result = Some(timing::time(Phase::Read, || load(path, cli)));
+                }
+                let Some(result) = result else {
+                    continue;
+                };
+                let (mut raw, encoding) = match result {
+                    Ok(Some(decoded)) => decoded,
+                    Ok(None) => {
+                        // Binary content can't match --grep or --changed, can't go in
+                        // a text patch, and --quiet-binary leaves no trace of it at all.
+                        if cli.quiet_binary || cli.as_patch || grep.is_some() || cli.changed {
+                            printer.omit_binary();
+                            continue;
+                        }
+                        eprintln!("=== {} === [binary, skipped]", path.display());
+                        if let Some(doc) = pending.take() {
+                            emit(&mut printer, &mut held, doc)?;
+                        }
+                        let placeholder = Document {
+                            files: vec![file],
+                            content: BINARY_PLACEHOLDER.to_string(),
+                            binary: true,
+                            encoding: None,
+                            note: None,
+                        };
+                        emit(&mut printer, &mut held, placeholder)?;
+                        continue;
+                    }
+                    Err(e) => {
+                        eprintln!("warning: cannot read {}: {e}", path.display());
+                        continue;
+                    }
+                };
+                if cli.show_dupes {
+                    let hash = sha256_hex(raw.as_bytes());
+                    let names = by_hash.entry(hash.clone()).or_default();
+                    if names.is_empty() {
+                        hashes.push(hash);
+                    }
+                    names.push(path.display().to_string());
+                }
+                if cli.skip_minified && lines::looks_minified(&raw, cli.minified_line_threshold) {
+                    eprintln!("skipping {} (looks minified)", path.display());
+                    continue;
+                }
+                if let Some(range) = file.lines {
+                    let span = range.span();
+                    raw = raw
+                        .split_inclusive('\n')
+                        .skip(span.start)
+                        .take(span.len())
+                        .collect();
+                }
+                // Recorded in the state file once the document is printed.
+                let mut recorded = None;
+                let note = match state.as_ref() {
+                    Some(state) => {
+                        let entry = (abs_path(path), file.lines);
+                        let (change, hash) = state.compare(&entry, &raw);
+                        let Some(note) = change.note() else {
+                            continue;
+                        };
+                        recorded = Some((entry, hash));
+                        Some(note)
+                    }
+                    None => None,
+                };
+                if cli.render_notebooks && has_extension(path, &["ipynb"]) {
+                    match notebook::render(&raw) {
+                        Some(cells) => raw = cells,
+                        None => eprintln!(
+                            "warning: {} is not a valid notebook; printing it as is",
+                            path.display()
+                        ),
+                    }
+                }
+                if cli.pretty_json && has_extension(path, &["json"]) {
+                    match transform::pretty_json(&raw) {
+                        Some(pretty) => raw = pretty,
+                        None => eprintln!(
+                            "warning: {} is not valid JSON; printing it as is",
+                            path.display()
+                        ),
+                    }
+                }
+                if cli.render_tables && has_extension(path, &["csv", "tsv"]) {
+                    let delimiter = match has_extension(path, &["tsv"]) {
+                        true => '\t',
+                        false => ',',
+                    };
+                    match transform::render_table(&raw, delimiter, cli.table_max_rows) {
+                        Some(table) => raw = table,
+                        None => eprintln!(
+                            "warning: {} is not valid CSV; printing it as is",
+                            path.display()
+                        ),
+                    }
+                }
+                if cli.frontmatter != Frontmatter::Keep && has_extension(path, &["md", "markdown"])
+                {
+                    let (front, body) = transform::split_frontmatter(&raw);
+                    raw = match cli.frontmatter {
+                        Frontmatter::Only => front.to_string(),
+                        _ => body.to_string(),
+                    };
+                }
+                if let (Some(width), true) =
+                    (cli.reflow_md, has_extension(path, &["md", "markdown"]))
+                {
+                    raw = transform::reflow_markdown(&raw, width);
+                }
+                if let Some(tag) = ContentType::lookup(cli, path).and_then(|t| t.section.as_ref()) {
+                    match transform::extract_sections(&raw, tag) {
+                        Some(section) => raw = section,
+                        None => eprintln!(
+                            "warning: {} has no <{tag}> block; printing it as is",
+                            path.display()
+                        ),
+                    }
+                }
+                if cli.strip_docstrings {
+                    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+                    raw = transform::strip_docstrings(&raw, ext);
+                }
+                if cli.strip_test_modules && has_extension(path, &["rs"]) {
+                    match testcode::strip_test_modules(&raw) {
+                        Ok(stripped) => raw = stripped,
+                        Err(e) => eprintln!(
+                            "warning: cannot parse {}: {e}; printing it with its tests",
+                            path.display()
+                        ),
+                    }
+                }
+                if let Some(re) = &grep {
+                    if !re.is_match(&raw) {
+                        continue;
+                    }
+                    if cli.match_summary {
+                        matches.push((path.display().to_string(), re.find_iter(&raw).count()));
+                    }
+                }
+                // Annotations keep one output line per input line, so spans computed
+                // on `raw` below still select the right lines of `content`.
+                let mut content = match cli.line_numbers {
+                    true => transform::format_with_line_numbers(
+                        &raw,
+                        file.lines.map_or(1, |range| range.start),
+                    ),
+                    false => raw.clone(),
+                };
+                if let Some(labels) = cli.blame.then(|| git::blame(path)).flatten() {
+                    // Labels are for the whole file; start them at the range.
+                    let skip = file.lines.map_or(0, |range| range.start - 1);
+                    content = transform::prefix_lines(&content, &labels[skip.min(labels.len())..]);
+                }
+                if let (Some(re), true) = (&grep, cli.auto_range) {
+                    content =
+                        lines::select(&content, &lines::match_spans(&raw, re, cli.context_lines));
+                }
+                if cli.changed {
+                    let dir = path.parent().filter(|p| !p.as_os_str().is_empty());
+                    if !git::in_work_tree(dir.unwrap_or(Path::new("."))) {
+                        eprintln!(
+                            "warning: skipping {} (not in a git work tree)",
+                            path.display()
+                        );
+                        continue;
+                    }
+                    let Some(spans) = git::changed_spans(path)? else {
+                        continue;
+                    };
+                    let spans = match file.lines {
+                        Some(range) => lines::clip(spans, range.span()),
+                        None => spans,
+                    };
+                    if spans.is_empty() {
+                        continue;
+                    }
+                    content = lines::select(&content, &lines::widen(spans, cli.context_lines));
+                }
+                if seen.as_ref().is_some_and(|s| s.contains(&raw)) {
+                    eprintln!("skipping {} (unchanged, already shared)", path.display());
+                    continue;
+                }
+                if let Some(keep) = cli.truncate_middle {
+                    content = lines::truncate_middle(&content, keep);
                 }
+                if cli.rewrite_abs_paths {
+                    content = content.replace(&abs_prefix, "./");
+                }
+                if cli.show_whitespace {
+                    content = transform::show_whitespace(&content);
+                }
+                if let Some(prefix) = &cli.line_prefix {
+                    content = transform::prefix_each(&content, prefix);
+                }
+                if let Some(prev) = pending.as_mut() {
+                    if cli.merge_identical && prev.content == content {
+                        prev.files.push(file);
+                        if let (Some(state), Some((entry, hash))) = (state.as_mut(), recorded) {
+                            state.record(entry, hash);
+                        }
+                        continue;
+                    }
+                }
+                // Set once this file is the last that fits in --max-total-lines.
+                let mut budget_hit: Option<(usize, Vec<FileEntry>)> = None;
+                if let Some(max) = cli.max_total_lines {
+                    let lines = content.lines().count();
+                    if total_lines + lines > max {
+                        let remaining = max - total_lines;
+                        let rest: Vec<FileEntry> = files.by_ref().collect();
+                        if cli.no_split_files || remaining == 0 {
+                            let mut omitted = vec![file];
+                            omitted.extend(rest);
+                            warn_line_budget(max, &omitted);
+                            break;
+                        }
+                        content = lines::head(&content, remaining);
+                        budget_hit = Some((max, rest));
+                    }
+                    total_lines += lines;
+                }
+                if let Some(max) = cli.fill_to_tokens {
+                    let tokens = tokens::estimate(&content);
+                    if fill.tokens + tokens > max {
+                        fill.left_out.push(file);
+                        fill.left_out.extend(files.by_ref());
+                        break;
+                    }
+                    fill.tokens += tokens;
+                    fill.included.push(file.path.clone());
+                }
+                if let Some(rules) = scanner.as_ref().map(|s| s.scan(&content)) {
+                    if !rules.is_empty() {
+                        if cli.fail_on_secret {
+                            anyhow::bail!(
+                                "refusing to print {}: it looks like it holds a secret ({})",
+                                path.display(),
+                                rules.join(", ")
+                            );
+                        }
+                        secrets_found.push((path.display().to_string(), rules));
+                    }
+                }
+                if let Some(seen) = seen.as_mut() {
+                    seen.insert(&raw);
+                }
+                if let (Some(state), Some((entry, hash))) = (state.as_mut(), recorded) {
+                    state.record(entry, hash);
+                }
+                if let Some(doc) = pending.replace(Document {
+                    files: vec![file],
+                    content,
+                    binary: false,
+                    encoding: Some(encoding),
+                    note,
+                }) {
+                    emit(&mut printer, &mut held, doc)?;
+                }
+                // Without merging there is nothing to wait for; print right away so
+                // a stalled read later on doesn't hold back finished output.
+                if !cli.merge_identical {
+                    if let Some(doc) = pending.take() {
+                        emit(&mut printer, &mut held, doc)?;
+                    }
+                }
+                if let Some((max, omitted)) = budget_hit {
+                    warn_line_budget(max, &omitted);
+                    break;
+                }
+            }
+            anyhow::Ok(())
+        })
+    })?;
+    bar.finish_and_clear();
+    if let Some(doc) = pending {
+        emit(&mut printer, &mut held, doc)?;
+    }
+    if let Some(docs) = held {
+        printer.expect(docs.len());
+        for doc in &docs {
+            timing::time(Phase::Render, || printer.print(doc))?;
+        }
+    }
+    timing::time(Phase::Render, || printer.finish())?;
+    if let Some(max) = cli.fill_to_tokens {
+        fill.report(max);
+    }
+    for (name, count) in &matches {
+        let noun = if *count == 1 { "match" } else { "matches" };
+        eprintln!("{name}: {count} {noun}");
+    }
+    let dupes: Vec<&Vec<String>> = hashes
+        .iter()
+        .map(|h| &by_hash[h])
+        .filter(|names| names.len() > 1)
+        .collect();
+    if cli.show_dupes && !dupes.is_empty() {
+        eprintln!("files with identical content:");
+        for names in dupes {
+            eprintln!("  {}", names.join(", "));
+        }
+    }
+    if !secrets_found.is_empty() {
+        eprintln!("warning: possible secrets in the output; check before sharing it:");
+        for (name, rules) in &secrets_found {
+            eprintln!("  {name}: {}", rules.join(", "));
+        }
+    }
+    if let Some(seen) = seen {
+        seen.save()?;
+    }
+    if let Some(state) = state {
+        state.save()?;
+    }
+
+    Ok(())
+}
+
+/// Print `doc`, or hold it back with the others when `held` is collecting.
+fn emit(printer: &mut Printer, held: &mut Option<Vec<Document>>, doc: Document) -> io::Result<()> {
+    match held {
+        Some(docs) => docs.push(doc),
+        None => timing::time(Phase::Render, || printer.print(&doc))?,
+    }
+    Ok(())
+}
+
+/// The nearest ancestor of `start` (itself included) containing any marker.
+fn find_project_root(start: &Path, markers: &[String]) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| markers.iter().any(|m| dir.join(m).exists()))
+        .map(Path::to_path_buf)
+}
+
+/// The `.fprignore` rules in effect: --ignore-file, or the nearest
+/// `.fprignore` in the current directory or above, unless --no-ignore-file.
+fn fprignore(cli: &Cli) -> anyhow::Result<Option<Gitignore>> {
+    if cli.no_ignore_file {
+        return Ok(None);
+    }
+    let file = match &cli.ignore_file {
+        Some(file) => file.clone(),
+        None => {
+            let cwd = std::env::current_dir()?;
+            match find_project_root(&cwd, &[".fprignore".to_string()]) {
+                Some(dir) => dir.join(".fprignore"),
+                None => return Ok(None),
             }
         }
+    };
+    // Rules are relative to the directory the file is found in, even when
+    // it's a symlink to one kept elsewhere, so only that directory is
+    // canonicalized.
+    let cannot_read =
+        |e: io::Error| anyhow::anyhow!("cannot read ignore file `{}`: {e}", file.display());
+    fs::metadata(&file).map_err(cannot_read)?;
+    let dir = match file.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    let root = fs::canonicalize(dir).map_err(cannot_read)?;
+    let Some(name) = file.file_name() else {
+        anyhow::bail!("invalid ignore file `{}`: not a file", file.display());
+    };
+    let file = root.join(name);
+    let mut builder = GitignoreBuilder::new(&root);
+    if let Some(err) = builder.add(&file) {
+        anyhow::bail!("invalid ignore file `{}`: {err}", file.display());
     }
+    Ok(Some(builder.build()?))
+}
 
-    files.sort();
-    files.dedup();
+/// What `--fill-to-tokens` let in and kept out.
+#[derive(Default)]
+struct TokenFill {
+    tokens: usize,
+    included: Vec<PathBuf>,
+    left_out: Vec<FileEntry>,
+}
 
-    let cwd = std::env::current_dir()?;
+impl TokenFill {
+    /// Tell the user which files fit in the budget and which didn't.
+    fn report(&self, max: usize) {
+        let names = |paths: &mut dyn Iterator<Item = &PathBuf>| {
+            paths
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        eprintln!(
+            "--fill-to-tokens {max}: {} file(s) fit in ~{} tokens: {}",
+            self.included.len(),
+            self.tokens,
+            names(&mut self.included.iter())
+        );
+        if !self.left_out.is_empty() {
+            eprintln!(
+                "left out {} file(s): {}",
+                self.left_out.len(),
+                names(&mut self.left_out.iter().map(|f| &f.path))
+            );
+        }
+    }
+}
+
+/// Tell the user which files `--max-total-lines` left out.
+fn warn_line_budget(max: usize, omitted: &[FileEntry]) {
+    if omitted.is_empty() {
+        return;
+    }
+    let names: Vec<String> = omitted
+        .iter()
+        .map(|f| f.path.display().to_string())
+        .collect();
+    eprintln!(
+        "warning: --max-total-lines {max} reached; omitted {} file(s): {}",
+        omitted.len(),
+        names.join(", ")
+    );
+}
+
+/// Read a list file: one input per line, blank lines ignored.
+fn read_list(path: &Path) -> anyhow::Result<Vec<String>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("cannot read list `{}`: {e}", path.display()))?;
+    Ok(parse_list(&text))
+}
+
+/// One entry per non-blank line, trimmed.
+fn parse_list(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// `--lists-dir`: bundle each `*.txt` list in `dir` into a file of the same
+/// name under `--output-dir`. A failing list doesn't stop the others; all
+/// failures are reported at the end.
+fn run_lists(cli: &Cli, dir: &Path) -> anyhow::Result<()> {
+    let out_dir = cli
+        .output_dir
+        .as_deref()
+        .expect("clap requires --output-dir with --lists-dir");
+    fs::create_dir_all(out_dir)?;
+    if fs::canonicalize(dir)? == fs::canonicalize(out_dir)? {
+        anyhow::bail!(
+            "--output-dir must differ from --lists-dir, or the lists would be overwritten"
+        );
+    }
+
+    let mut lists: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == "txt"))
+        .collect();
+    lists.sort();
+
+    let mut failures = Vec::new();
+    for list in &lists {
+        let result = read_list(list).and_then(|inputs| {
+            // Buffer so a failing list leaves no half-written bundle behind.
+            let mut buf = Vec::new();
+            bundle(cli, &inputs, Box::new(&mut buf))?;
+            let target = out_dir.join(list.file_name().expect("listed files have names"));
+            Ok(fs::write(target, encode_output(cli, buf))?)
+        });
+        if let Err(e) = result {
+            failures.push(format!("{}: {e}", list.display()));
+        }
+    }
 
-    for (idx, path) in files.iter().enumerate() {
-        let rel = path.strip_prefix(&cwd).unwrap_or(path);
-        println!("=== {} ===", rel.display());
-        let content = fs::read_to_string(path)?;
-        print!("{content}");
+    if !failures.is_empty() {
+        for failure in &failures {
+            eprintln!("{failure}");
+        }
+        anyhow::bail!("{} of {} lists failed", failures.len(), lists.len());
+    }
+    Ok(())
+}
+
+/// Resolve one command-line input, already group-expanded, into the files
+/// it names.
+fn collect_input(group: &GroupExpansion, cli: &Cli) -> anyhow::Result<Vec<FileEntry>> {
+    // Each file, with the include that pins it against exclusion (if any).
+    let mut files: Vec<(FileEntry, Option<PathBuf>)> = Vec::new();
+    let excludes = &group.excludes;
+
+    // 1. Handle each pattern the grouping syntax produced.
+    for pat in &group.includes {
+        // Under include-wins, a plain-path include overrides any exclusion
+        // that contains it.
+        let pin = (cli.group_precedence == Precedence::IncludeWins && !is_glob(pat))
+            .then(|| normalize(Path::new(pat)));
+        let found = if is_glob(pat) {
+            let mut found = Vec::new();
+            timing::time(Phase::Glob, || expand_glob(pat, cli, &mut found))?;
+            found.sort();
+            found
+                .into_iter()
+                .map(|path| FileEntry {
+                    path,
+                    root: None,
+                    explicit: false,
+                    lines: None,
+                })
+                .collect()
+        } else {
+            collect_path(Path::new(pat), cli)?
+        };
+        files.extend(found.into_iter().map(|entry| (entry, pin.clone())));
+    }
+
+    // 2. Exclusions may be globs or directories, so resolve them to paths
+    //    and drop anything at or beneath them.
+    if !excludes.is_empty() {
+        let excluded = resolve_excludes(excludes, cli)?;
+        files.retain(|(f, pin)| {
+            let path = normalize(&f.path);
+            !excluded
+                .iter()
+                .any(|x| path.starts_with(x) && !pin.as_ref().is_some_and(|p| p.starts_with(x)))
+        });
+    }
+    Ok(files.into_iter().map(|(f, _)| f).collect())
+}
+
+/// Collect a plain path: a file as is, or everything a walk of a directory
+/// finds.
+fn collect_path(path: &Path, cli: &Cli) -> anyhow::Result<Vec<FileEntry>> {
+    if path.is_dir() {
+        let mut found = Vec::new();
+        timing::time(Phase::Walk, || expand_dir(path, cli, &mut found))?;
+        found.sort();
+        Ok(found
+            .into_iter()
+            .map(|file| FileEntry {
+                path: file,
+                root: Some(path.to_path_buf()),
+                explicit: false,
+                lines: None,
+            })
+            .collect())
+    } else if path.is_file()
+        || fs::metadata(path).is_ok_and(|m| read::is_special(m.file_type()))
+            && special_allowed(path, cli)
+    {
+        Ok(vec![FileEntry {
+            path: path.to_path_buf(),
+            root: None,
+            explicit: !cli.strict_filters,
+            lines: None,
+        }])
+    } else if path.exists() {
+        // A special file that was skipped, with a warning.
+        Ok(Vec::new())
+    } else {
+        anyhow::bail!("Input `{}` does not exist", path.display())
+    }
+}
+
+/// Resolve group exclusions to the (normalized) paths they cover. Excluded
+/// paths that don't exist simply match nothing.
+fn resolve_excludes(excludes: &[String], cli: &Cli) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for pat in excludes {
+        if is_glob(pat) {
+            timing::time(Phase::Glob, || expand_glob(pat, cli, &mut paths))?;
+        } else if Path::new(pat).exists() {
+            paths.push(PathBuf::from(pat));
+        }
+    }
+    Ok(paths.iter().map(|p| normalize(p)).collect())
+}
+
+/// Drop `.` components so `./src/a.rs` and `src/a.rs` compare equal.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, std::path::Component::CurDir))
+        .collect()
+}
 
-        if idx + 1 < files.len() {
-            println!();
-            println!("{}", cli.separator);
-            println!();
+/// `--diff`: compare the two inputs, in the order given.
+fn run_diff(cli: &Cli) -> anyhow::Result<()> {
+    let [old, new] = cli.inputs.as_slice() else {
+        anyhow::bail!("--diff needs exactly two files, got {}", cli.inputs.len());
+    };
+    let old_content = read_text(Path::new(old))?;
+    let new_content = read_text(Path::new(new))?;
+    print!(
+        "{}",
+        diff::unified(old, &old_content, new, &new_content, cli.color.enabled())
+    );
+    Ok(())
+}
+
+/// `--diff-against`: diff each resolved file against the file at the same
+/// relative path under `dir`, printing only those that differ. Files missing
+/// from `dir` are reported as added; files only in `dir` are reported as
+/// removed when they lie beneath one of the directory inputs.
+fn run_diff_against(cli: &Cli, inputs: &[String], dir: &Path) -> anyhow::Result<()> {
+    let files = resolve(cli, inputs)?;
+    let cwd = std::env::current_dir()?;
+    let relative = |path: &Path| {
+        let path = normalize(path);
+        match path.strip_prefix(&cwd) {
+            Ok(rel) => rel.to_path_buf(),
+            Err(_) => path,
         }
+    };
+    let color = cli.color.enabled();
+    let mut out = io::stdout();
+
+    let mut ours = HashSet::new();
+    let mut roots = BTreeSet::new();
+    for file in &files {
+        let name = relative(&file.path);
+        if let Some(root) = &file.root {
+            roots.insert(relative(root));
+        }
+        let theirs = dir.join(&name);
+        if !theirs.is_file() {
+            writeln!(out, "added: {}", name.display())?;
+        } else if let (Some(old), Some(new)) = (read_file(&theirs)?, read_file(&file.path)?) {
+            let old_name = theirs.display().to_string();
+            let new_name = name.display().to_string();
+            write!(
+                out,
+                "{}",
+                diff::unified(&old_name, &old, &new_name, &new, color)
+            )?;
+        } else if fs::read(&theirs)? != fs::read(&file.path)? {
+            writeln!(
+                out,
+                "binary files {} and {} differ",
+                theirs.display(),
+                name.display()
+            )?;
+        }
+        ours.insert(name);
     }
 
+    for root in roots {
+        let base = dir.join(&root);
+        if !base.is_dir() {
+            continue;
+        }
+        let mut found = Vec::new();
+        expand_dir(&base, cli, &mut found)?;
+        found.sort();
+        for path in found {
+            let name = path.strip_prefix(dir).unwrap_or(&path);
+            if !ours.contains(name) {
+                writeln!(out, "removed: {}", name.display())?;
+            }
+        }
+    }
     Ok(())
 }
 
-/// Heuristic: does the string look like a glob?
+/// Abort the whole run once `limit` has elapsed, flushing what was printed.
+fn spawn_watchdog(limit: Duration) {
+    std::thread::spawn(move || {
+        std::thread::sleep(limit);
+        // Flushing waits for stdout's lock, which a write stuck on a full
+        // pipe could hold forever, so it's given a moment and no more.
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let _ = io::stdout().flush();
+            let _ = tx.send(());
+        });
+        let _ = rx.recv_timeout(Duration::from_millis(200));
+        eprintln!("error: timed out after {}s", limit.as_secs_f64());
+        std::process::exit(TIMEOUT_EXIT_CODE);
+    });
+}
+
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    let secs: f64 = s
+        .parse()
+        .map_err(|_| format!("`{s}` is not a number of seconds"))?;
+    Duration::try_from_secs_f64(secs).map_err(|e| e.to_string())
+}
+
+fn has_size_limit(cli: &Cli) -> bool {
+    cli.max_size.is_some() || cli.min_size.is_some()
+}
+
+/// Is a file of `len` bytes within --min-size and --max-size? Says why not
+/// on stderr.
+fn size_allowed(path: &Path, len: u64, cli: &Cli) -> bool {
+    let (op, limit) = match (cli.min_size, cli.max_size) {
+        (Some(min), _) if len < min => ("<", min),
+        (_, Some(max)) if len > max => (">", max),
+        _ => return true,
+    };
+    eprintln!(
+        "skipping {} ({} {op} {})",
+        path.display(),
+        format_size(len),
+        format_size(limit)
+    );
+    false
+}
+
+/// Is `path` within --max-lines? Counts newlines without decoding, stopping
+/// as soon as the limit is passed, and says so on stderr when it is.
+fn lines_allowed(path: &Path, cli: &Cli) -> io::Result<bool> {
+    let Some(max) = cli.max_lines else {
+        return Ok(true);
+    };
+    let mut reader = io::BufReader::new(fs::File::open(path)?);
+    let mut lines = 0;
+    let mut mid_line = false;
+    loop {
+        let buf = io::BufRead::fill_buf(&mut reader)?;
+        if buf.is_empty() {
+            break;
+        }
+        lines += buf.iter().filter(|&&b| b == b'\n').count();
+        mid_line = buf.last() != Some(&b'\n');
+        let len = buf.len();
+        io::BufRead::consume(&mut reader, len);
+        if lines > max {
+            break;
+        }
+    }
+    if lines + usize::from(mid_line) <= max {
+        return Ok(true);
+    }
+    eprintln!("skipping {} (more than {max} lines)", path.display());
+    Ok(false)
+}
+
+/// Parse a byte count with an optional binary suffix: `512k`, `2M`, `1G`.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let (digits, scale) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&s[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    let n: u64 = digits
+        .parse()
+        .map_err(|_| format!("`{s}` is not a size like 512k, 2M or 1G"))?;
+    n.checked_mul(scale)
+        .ok_or_else(|| format!("`{s}` is too large"))
+}
+
+/// A byte count for humans, e.g. `503MB` or `1.5KB`.
+fn format_size(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < units.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    let value = format!("{value:.1}");
+    format!("{}{}", value.trim_end_matches(".0"), units[unit])
+}
+
+/// Does `path` end in one of `exts` (compared case-insensitively)?
+fn has_extension(path: &Path, exts: &[&str]) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| exts.iter().any(|x| x.eq_ignore_ascii_case(e)))
+}
+
+/// What identifies `path` when dropping duplicates: its fully resolved
+/// path, or the path as given if it can't be resolved.
+fn dedup_key(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// `path` with its directory canonicalized but its file name kept, so a
+/// symlink stays the link rather than becoming its target.
+fn canonical_path(path: &Path) -> Option<PathBuf> {
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    Some(fs::canonicalize(dir).ok()?.join(path.file_name()?))
+}
+
+/// Read `path` as text, failing if it is binary.
+fn read_text(path: &Path) -> anyhow::Result<String> {
+    read_file(path)?.ok_or_else(|| anyhow::anyhow!("`{}` is a binary file", path.display()))
+}
+
+/// Heuristic: does the string look like a glob? A path that exists as
+/// written, like `notes[draft].md`, is taken literally.
 fn is_glob(s: &str) -> bool {
-    s.contains('*') || s.contains('?') || s.contains('[')
+    (s.contains('*') || s.contains('?') || s.contains('[')) && !Path::new(s).exists()
 }
 
 /// Expand a glob pattern into actual file paths.
-fn expand_glob(pattern: &str, out: &mut Vec<PathBuf>) -> anyhow::Result<()> {
-    let walker = GlobWalkerBuilder::from_patterns(".", &[pattern])
-        .case_insensitive(false)
-        .build()
-        .map_err(|e| anyhow::anyhow!("invalid glob `{pattern}`: {e}"))?;
+fn expand_glob(pattern: &str, cli: &Cli, out: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let matcher = glob_matcher(".", &[pattern])?;
 
-    for entry in walker
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-    {
-        out.push(entry.into_path());
+    for entry in walker(Path::new("."), cli).build() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                handle_walk_error(err, cli)?;
+                continue;
+            }
+        };
+        if matcher.matched(entry.path(), false).is_whitelist()
+            && walked_file(&entry, cli)
+            && walked_size_allowed(&entry, cli)
+        {
+            out.push(entry.into_path());
+        }
     }
     Ok(())
 }
 
+/// Is `pattern` a recursive glob with nothing else narrowing it, like `**`
+/// or `./**/*`?
+fn is_sweeping_glob(pattern: &str) -> bool {
+    let parts: Vec<&str> = pattern
+        .split('/')
+        .filter(|p| !p.is_empty() && *p != ".")
+        .collect();
+    parts.contains(&"**") && parts.iter().all(|p| matches!(*p, "**" | "*"))
+}
+
+/// A matcher for paths under `root` that matches any of `patterns`.
+fn glob_matcher(root: &str, patterns: &[impl AsRef<str>]) -> anyhow::Result<Override> {
+    let mut matcher = OverrideBuilder::new(root);
+    for pattern in patterns {
+        let pattern = pattern.as_ref();
+        matcher
+            .add(pattern)
+            .map_err(|e| anyhow::anyhow!("invalid glob `{pattern}`: {e}"))?;
+    }
+    matcher
+        .build()
+        .map_err(|e| anyhow::anyhow!("invalid glob: {e}"))
+}
+
 /// Recurse through a directory (optionally deeply) collecting files.
-fn expand_dir(dir: &Path, recursive: bool, out: &mut Vec<PathBuf>) -> anyhow::Result<()> {
-    if recursive {
-        for entry in WalkDir::new(dir)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
-        {
+fn expand_dir(dir: &Path, cli: &Cli, out: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let mut walker = walker(dir, cli);
+    if !cli.recursive {
+        walker.max_depth(Some(cli.max_depth.map_or(1, |d| d.min(1))));
+    }
+    for entry in walker.build() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                handle_walk_error(err, cli)?;
+                continue;
+            }
+        };
+        if walked_file(&entry, cli) && walked_size_allowed(&entry, cli) {
             out.push(entry.into_path());
         }
-    } else {
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_file() {
-                out.push(path);
+    }
+    Ok(())
+}
+
+/// Is this walked entry something to collect: a regular file, or a FIFO,
+/// socket or device under --allow-special?
+fn walked_file(entry: &ignore::DirEntry, cli: &Cli) -> bool {
+    match entry.file_type() {
+        Some(t) if t.is_file() => true,
+        Some(t) if read::is_special(t) => special_allowed(entry.path(), cli),
+        _ => false,
+    }
+}
+
+/// Whether a FIFO, socket or device may be read, warning when it's skipped.
+fn special_allowed(path: &Path, cli: &Cli) -> bool {
+    if !cli.allow_special {
+        eprintln!("skipping {} (not a regular file)", path.display());
+    }
+    cli.allow_special
+}
+
+/// Apply the size limits while walking, so files that would be dropped
+/// anyway are never collected. Files that can't be stat-ed are kept for the
+/// read to report.
+fn walked_size_allowed(entry: &ignore::DirEntry, cli: &Cli) -> bool {
+    if !has_size_limit(cli) {
+        return true;
+    }
+    match entry.metadata() {
+        Ok(meta) => size_allowed(entry.path(), meta.len(), cli),
+        Err(_) => true,
+    }
+}
+
+/// A walk over `root` that applies the options every walk shares: symlink
+/// following, --max-depth, pruned directories, and `.gitignore` rules
+/// (nested ones, `.git/info/exclude` and the global `core.excludesFile`)
+/// unless --no-gitignore. Hidden directories and files are skipped unless
+/// --hidden-dirs / --hidden-files ask for them, and submodules unless
+/// --submodules does.
+fn walker(root: &Path, cli: &Cli) -> WalkBuilder {
+    let gitignore = !cli.no_gitignore;
+    let excluded = excluded_dirs(cli);
+    let submodules = match cli.submodules {
+        true => HashSet::new(),
+        false => git::submodules(root),
+    };
+    let (hidden_dirs, hidden_files) = (cli.hidden_dirs, cli.hidden_files);
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .max_depth(cli.max_depth)
+        .follow_links(cli.follow_symlinks)
+        .hidden(false)
+        .ignore(false)
+        .parents(gitignore)
+        .git_ignore(gitignore)
+        .git_exclude(gitignore)
+        .git_global(gitignore)
+        .require_git(false)
+        // Never prune the input itself, only what's found beneath it.
+        .filter_entry(move |e| {
+            if e.depth() == 0 {
+                return true;
+            }
+            let is_dir = e.file_type().is_some_and(|t| t.is_dir());
+            let name = e.file_name().to_string_lossy();
+            if name.starts_with('.') && !(if is_dir { hidden_dirs } else { hidden_files }) {
+                return false;
+            }
+            if is_dir && !submodules.is_empty() {
+                let path = fs::canonicalize(e.path());
+                if path.is_ok_and(|p| submodules.contains(&p)) {
+                    return false;
+                }
             }
+            !(is_dir && excluded.iter().any(|x| *x == name))
+        });
+    builder
+}
+
+/// Directory names to prune while walking: `--exclude-dir` plus `--exclude-vcs`.
+fn excluded_dirs(cli: &Cli) -> Vec<String> {
+    let mut names = cli.exclude_dir.clone();
+    if cli.exclude_vcs {
+        names.extend(VCS_DIRS.iter().map(|d| d.to_string()));
+    }
+    names
+}
+
+/// Decide what to do with an error yielded by a directory walk.
+///
+/// Symlink loops are reported (or fatal with `--abort-on-symlink-loop`);
+/// anything else, such as an unreadable entry, is skipped as before.
+fn handle_walk_error(err: ignore::Error, cli: &Cli) -> anyhow::Result<()> {
+    if let Some((ancestor, link)) = symlink_loop(&err) {
+        let msg = format!(
+            "symlink loop: `{}` points back to `{}`",
+            link.display(),
+            ancestor.display()
+        );
+        if cli.abort_on_symlink_loop {
+            anyhow::bail!(msg);
         }
+        eprintln!("warning: {msg}, not descending");
     }
     Ok(())
 }
 
+/// The `(ancestor, link)` of a symlink loop, however deeply `err` wraps it.
+fn symlink_loop(err: &ignore::Error) -> Option<(&Path, &Path)> {
+    match err {
+        ignore::Error::Loop { ancestor, child } => Some((ancestor, child)),
+        ignore::Error::WithPath { err, .. }
+        | ignore::Error::WithDepth { err, .. }
+        | ignore::Error::WithLineNumber { err, .. } => symlink_loop(err),
+        _ => None,
+    }
+}
+
 // ───────────────────────────────── GROUP SYNTAX ─────────────────────────────
 
+/// The result of expanding one grouped argument.
+struct GroupExpansion {
+    /// Path or glob strings to resolve, with literal exclusions already removed.
+    includes: Vec<String>,
+    /// Excluded path or glob strings, applied again to the resolved files so
+    /// that `src/(*.rs, -main.rs)` drops what the glob branch matched.
+    excludes: Vec<String>,
+}
+
+/// What's left of one input's --max-expansions budget as its brace
+/// alternatives are group-expanded in turn.
+struct ExpansionLimit<'a> {
+    left: usize,
+    max: usize,
+    /// The input to name when the budget runs out.
+    input: &'a str,
+}
+
+impl ExpansionLimit<'_> {
+    /// Fail unless `count` more paths fit in the budget.
+    fn check(&self, count: usize) -> anyhow::Result<()> {
+        if count > self.left {
+            anyhow::bail!(
+                "`{}` expands to more than {} paths; raise --max-expansions to allow it",
+                self.input,
+                self.max
+            );
+        }
+        Ok(())
+    }
+
+    /// Spend `count` paths of the budget.
+    fn take(&mut self, count: usize) -> anyhow::Result<()> {
+        self.check(count)?;
+        self.left -= count;
+        Ok(())
+    }
+}
+
 /// Expand a single argument that may use parenthetical grouping and exclusions.
-/// Returns a list of concrete path or glob strings **after** applying exclusions.
-fn expand_group_pattern(pattern: &str) -> anyhow::Result<Vec<String>> {
-    // Inner recursive function that builds (string, is_excluded) pairs.
-    fn expand_rec(span: &str) -> anyhow::Result<Vec<(String, bool)>> {
+/// Returns the concrete path or glob strings **after** applying exclusions.
+/// On Windows, `\` path separators are normalized to `/` in the results.
+fn expand_group_pattern(
+    pattern: &str,
+    precedence: Precedence,
+    limit: &ExpansionLimit,
+) -> anyhow::Result<GroupExpansion> {
+    // Inner recursive function that builds (string, is_excluded) pairs,
+    // failing as soon as there would be more than the limit allows.
+    fn expand_rec(span: &str, limit: &ExpansionLimit) -> anyhow::Result<Vec<(String, bool)>> {
         let mut acc: Vec<(String, bool)> = vec![(String::new(), false)];
         let chars: Vec<char> = span.chars().collect();
         let mut i = 0;
 
         while i < chars.len() {
             match chars[i] {
+                '\\' if is_escape(&chars, i) => {
+                    for (s, _) in &mut acc {
+                        s.push(chars[i + 1]);
+                    }
+                    i += 2;
+                }
                 '(' => {
                     // Parse group and combine cartesian‑style.
-                    let (group_items, next_i) = parse_group(&chars, i + 1)?;
+                    let (group_items, next_i) = parse_group(&chars, i + 1, limit)?;
+                    limit.check(acc.len().saturating_mul(group_items.len()))?;
                     let mut new_acc = Vec::new();
                     for (prefix, pref_excl) in &acc {
                         for (suffix, suff_excl) in &group_items {
@@ -171,14 +3010,28 @@ fn expand_group_pattern(pattern: &str) -> anyhow::Result<Vec<String>> {
         Ok(acc)
     }
 
+    /// Is `chars[i]` a backslash escaping a delimiter (or another backslash)?
+    /// Not on Windows, where `\` separates paths instead.
+    fn is_escape(chars: &[char], i: usize) -> bool {
+        !cfg!(windows)
+            && chars[i] == '\\'
+            && matches!(chars.get(i + 1), Some('(' | ')' | ',' | '\\'))
+    }
+
     /// Parse the comma‑separated list inside a `(` … `)`.
-    fn parse_group(chars: &[char], mut i: usize) -> anyhow::Result<(Vec<(String, bool)>, usize)> {
+    fn parse_group(
+        chars: &[char],
+        mut i: usize,
+        limit: &ExpansionLimit,
+    ) -> anyhow::Result<(Vec<(String, bool)>, usize)> {
         let mut segments: Vec<String> = Vec::new();
         let mut depth = 0;
         let mut start = i;
 
         while i < chars.len() {
             match chars[i] {
+                // Left in place for `expand_rec` to unescape.
+                '\\' if is_escape(chars, i) => i += 2,
                 '(' => {
                     depth += 1;
                     i += 1;
@@ -217,7 +3070,8 @@ fn expand_group_pattern(pattern: &str) -> anyhow::Result<Vec<String>> {
             } else {
                 (false, trimmed)
             };
-            let sub_items = expand_rec(body)?;
+            let sub_items = expand_rec(body, limit)?;
+            limit.check(out.len() + sub_items.len())?;
             for (s, sub_excl) in sub_items {
                 out.push((s, is_excl || sub_excl));
             }
@@ -226,11 +3080,18 @@ fn expand_group_pattern(pattern: &str) -> anyhow::Result<Vec<String>> {
     }
 
     // Kick off recursive expansion for the full pattern.
-    let pairs = expand_rec(pattern)?;
+    let pairs = expand_rec(pattern, limit)?;
     let mut includes = Vec::new();
     let mut excludes: HashSet<String> = HashSet::new();
 
     for (s, excl) in pairs {
+        // Windows users write `src\(a, b)`; emit forward slashes so branches
+        // built from either separator compare (and exclude) consistently.
+        let s = if cfg!(windows) {
+            s.replace('\\', "/")
+        } else {
+            s
+        };
         if excl {
             excludes.insert(s);
         } else {
@@ -239,6 +3100,10 @@ fn expand_group_pattern(pattern: &str) -> anyhow::Result<Vec<String>> {
     }
 
     // Remove any includes that were marked for exclusion.
-    includes.retain(|p| !excludes.contains(p));
-    Ok(includes)
-}
\ No newline at end of file
+    if precedence == Precedence::ExcludeWins {
+        includes.retain(|p| !excludes.contains(p));
+    }
+    let mut excludes: Vec<String> = excludes.into_iter().collect();
+    excludes.sort();
+    Ok(GroupExpansion { includes, excludes })
+}
@@ -0,0 +1,32 @@
+// src/notebook.rs
+//! Rendering Jupyter notebooks as their cell sources.
+
+use serde_json::Value;
+
+/// The sources of a notebook's code and markdown cells, in order and
+/// separated by blank lines. Outputs and metadata are dropped. Returns
+/// `None` when `json` doesn't look like a notebook.
+pub fn render(json: &str) -> Option<String> {
+    let doc: Value = serde_json::from_str(json).ok()?;
+    let mut cells = Vec::new();
+    for cell in doc.get("cells")?.as_array()? {
+        match cell.get("cell_type")?.as_str()? {
+            "code" | "markdown" => {}
+            _ => continue,
+        }
+        // `source` is either one string or a list of line strings.
+        let mut source = match cell.get("source")? {
+            Value::String(s) => s.clone(),
+            Value::Array(parts) => parts
+                .iter()
+                .map(Value::as_str)
+                .collect::<Option<String>>()?,
+            _ => return None,
+        };
+        if !source.ends_with('\n') {
+            source.push('\n');
+        }
+        cells.push(source);
+    }
+    Some(cells.join("\n"))
+}
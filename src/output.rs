@@ -0,0 +1,768 @@
+// src/output.rs
+//! Rendering documents to the output stream.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use encoding_rs::Encoding;
+use serde_json::{Map, Value};
+
+use crate::checksum::sha256_hex;
+use crate::{
+    format_size, normalize, patch, tree, Cli, ContentType, Document, FileEntry, JsonField,
+};
+
+/// Wraps a writer, counting the newlines that pass through it.
+struct LineCounter<W> {
+    inner: W,
+    newlines: usize,
+}
+
+impl<W: Write> Write for LineCounter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.newlines += buf[..n].iter().filter(|&&b| b == b'\n').count();
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Where a document's content landed in the output, for `--index-footer`.
+struct IndexEntry {
+    name: String,
+    /// 1-based, inclusive line range; `None` for empty content.
+    lines: Option<(usize, usize)>,
+}
+
+/// Prints documents one after another with headers and separators.
+pub struct Printer<'a> {
+    cli: &'a Cli,
+    cwd: PathBuf,
+    /// What displayed paths are relative to: `cwd`, or the `--smart-root`.
+    base: PathBuf,
+    out: LineCounter<Box<dyn Write + 'a>>,
+    printed: usize,
+    /// Whether the last thing written left the output mid-line.
+    mid_line: bool,
+    /// Extension of the previous document, for `--quiet-separators`.
+    last_ext: Option<OsString>,
+    index: Vec<IndexEntry>,
+    /// How many documents are expected, for `--numbered-separators`.
+    total: usize,
+    /// Everything printed so far, for `--json` to write out at the end.
+    json: Vec<Value>,
+    /// Files, lines and bytes of text printed, for `--stats`.
+    totals: (usize, usize, usize),
+    /// Each real path printed and its placeholder, for `--anonymize-paths`.
+    aliases: Vec<(String, String)>,
+    /// How wide to pad header names, for `--pad-headers`.
+    header_width: usize,
+    /// Binary files left out without a trace, for `--stats` to mention.
+    binaries_omitted: usize,
+    /// What was printed, by extension, for `--ext-stats` under `--stats`.
+    ext_stats: ExtStats,
+}
+
+impl<'a> Printer<'a> {
+    pub fn new(cli: &'a Cli, cwd: PathBuf, base: PathBuf, out: Box<dyn Write + 'a>) -> Self {
+        Printer {
+            cli,
+            cwd,
+            base,
+            out: LineCounter {
+                inner: out,
+                newlines: 0,
+            },
+            printed: 0,
+            mid_line: false,
+            last_ext: None,
+            index: Vec::new(),
+            total: 0,
+            json: Vec::new(),
+            totals: (0, 0, 0),
+            aliases: Vec::new(),
+            header_width: 0,
+            binaries_omitted: 0,
+            ext_stats: ExtStats::default(),
+        }
+    }
+
+    /// Set how many documents the separators should count towards.
+    pub fn expect(&mut self, total: usize) {
+        self.total = total;
+    }
+
+    /// Note a binary file left out without a placeholder.
+    pub fn omit_binary(&mut self) {
+        self.binaries_omitted += 1;
+    }
+
+    /// Pad headers to the longest name among `files`, plus the checksum
+    /// every header carries with --inline-checksum (digests are all the same
+    /// length). Headers longer than that, with merged names, just go
+    /// unpadded.
+    pub fn pad_headers(&mut self, files: &[FileEntry]) {
+        let checksum = self
+            .cli
+            .inline_checksum
+            .map_or(0, |algo| format!(" [{}]", algo.label(b"")).len());
+        self.header_width = files
+            .iter()
+            .map(|f| {
+                header_name(f, self.cli, &self.cwd, &self.base)
+                    .chars()
+                    .count()
+            })
+            .max()
+            .unwrap_or(0)
+            + checksum;
+    }
+
+    /// Print one document, preceded by the separator unless it is the first.
+    pub fn print(&mut self, doc: &Document) -> io::Result<()> {
+        let cli = self.cli;
+        if !doc.binary {
+            self.totals.0 += doc.files.len();
+            self.totals.1 += doc.content.lines().count();
+            self.totals.2 += doc.content.len();
+        }
+        if cli.ext_stats {
+            for file in &doc.files {
+                match doc.binary {
+                    true => {
+                        let len = fs::metadata(&file.path).map_or(0, |m| m.len());
+                        self.ext_stats.add(&file.path, len, 0);
+                    }
+                    false => {
+                        let (len, lines) = (doc.content.len(), doc.content.lines().count());
+                        self.ext_stats.add(&file.path, len as u64, lines);
+                    }
+                }
+            }
+        }
+        if cli.as_patch {
+            let file = &doc.files[0];
+            let name = self.portable_name(file);
+            let executable = file_mode(&file.path).is_some_and(|m| m & 0o111 != 0);
+            return write!(
+                self.out,
+                "{}",
+                patch::new_file(&name, &doc.content, executable)
+            );
+        }
+        if cli.json {
+            for file in &doc.files {
+                let entry = self.json_entry(file, doc);
+                self.json.push(entry);
+            }
+            return Ok(());
+        }
+        if cli.list {
+            for file in &doc.files {
+                let name = self.name_of(file);
+                let end = if cli.null { '\0' } else { '\n' };
+                write!(self.out, "{name}{end}")?;
+            }
+            return Ok(());
+        }
+        if cli.single {
+            return self.print_inline(doc);
+        }
+        self.separate(&doc.files[0])?;
+        let names: Vec<String> = doc.files.iter().map(|f| self.name_of(f)).collect();
+        let mut header = names.join(", ");
+        if let (true, Some(encoding)) = (cli.show_encoding, doc.encoding) {
+            header.push_str(&encoding_note(encoding));
+        }
+        if let Some(note) = doc.note {
+            header.push_str(&format!(" ({note})"));
+        }
+        if let Some(algo) = cli.inline_checksum {
+            header.push_str(&format!(" [{}]", algo.label(doc.content.as_bytes())));
+        }
+        self.header(&header)?;
+        if cli.collapsible {
+            writeln!(
+                self.out,
+                "<details><summary>{} ({})</summary>\n",
+                escape_html(&names.join(", ")),
+                format_size(doc.content.len() as u64)
+            )?;
+        }
+        let fence = cli.markdown.then(|| fence_for(&doc.content));
+        if let Some(fence) = &fence {
+            let lang = language(cli, &doc.files[0].path, &doc.content);
+            writeln!(self.out, "{fence}{lang}")?;
+        }
+        let marker = cli.split_markers.then(|| self.portable_name(&doc.files[0]));
+        if let Some(name) = &marker {
+            writeln!(self.out, "<<<FILE: {name}>>>")?;
+        }
+
+        // Markers keep the content exactly, for `unbundle`; otherwise it ends
+        // in exactly one newline, however many (or few) the file had, so the
+        // spacing around separators is always the same.
+        let content = match marker {
+            Some(_) => doc.content.clone(),
+            None => with_one_newline(&doc.content),
+        };
+        let first_line = self.out.newlines + 1;
+        write!(self.out, "{content}")?;
+        if content.is_empty() && cli.mark_empty_files {
+            writeln!(self.out, "(empty file)")?;
+        }
+        if let Some(name) = &marker {
+            // The end marker goes on its own line, so say when the content
+            // didn't end with a newline for `unbundle` to restore it exactly.
+            if content.is_empty() || content.ends_with('\n') {
+                writeln!(self.out, "<<<END FILE: {name}>>>")?;
+            } else {
+                writeln!(self.out, "\n<<<END FILE NOEOL: {name}>>>")?;
+            }
+        }
+        if let Some(fence) = &fence {
+            writeln!(self.out, "{fence}")?;
+        }
+        if cli.collapsible {
+            writeln!(self.out, "\n</details>")?;
+        }
+        let line_count = content.lines().count();
+        self.index.push(IndexEntry {
+            name: names.join(", "),
+            lines: (line_count > 0).then(|| (first_line, first_line + line_count - 1)),
+        });
+        self.mid_line = false;
+        self.printed += 1;
+        Ok(())
+    }
+
+    /// Print `file` with its content written by `body` straight from disk,
+    /// for files too big to hold in memory. `body` ends the content in one
+    /// newline, as `print` does, and returns how many bytes it wrote.
+    pub fn print_streamed(
+        &mut self,
+        file: &FileEntry,
+        body: impl FnOnce(&mut dyn Write) -> io::Result<usize>,
+    ) -> io::Result<()> {
+        self.separate(file)?;
+        let mut header = self.name_of(file);
+        if self.cli.show_encoding {
+            // Only BOM-less files are streamed.
+            header.push_str(&encoding_note(encoding_rs::UTF_8));
+        }
+        self.header(&header)?;
+        let before = self.out.newlines;
+        // Buffered here rather than line by line on the way to stdout.
+        let mut out = io::BufWriter::new(&mut self.out);
+        let bytes = body(&mut out)?;
+        out.flush()?;
+        drop(out);
+        self.totals.0 += 1;
+        self.totals.1 += self.out.newlines - before;
+        self.totals.2 += bytes;
+        if self.cli.ext_stats {
+            let lines = self.out.newlines - before;
+            self.ext_stats.add(&file.path, bytes as u64, lines);
+        }
+        self.mid_line = false;
+        self.printed += 1;
+        Ok(())
+    }
+
+    /// The separator ahead of every document but the first.
+    fn separate(&mut self, file: &FileEntry) -> io::Result<()> {
+        let cli = self.cli;
+        let ext = file.path.extension().map(OsString::from);
+        if self.printed > 0 {
+            writeln!(self.out)?;
+            if !cli.quiet_separators || ext != self.last_ext {
+                if cli.numbered_separators {
+                    let sep = &cli.separator;
+                    let n = self.printed + 1;
+                    writeln!(self.out, "{sep} [{n}/{}] {sep}", self.total.max(n))?;
+                } else {
+                    writeln!(self.out, "{}", cli.separator)?;
+                }
+                writeln!(self.out)?;
+            }
+        }
+        self.last_ext = ext;
+        Ok(())
+    }
+
+    /// The header line naming a document.
+    fn header(&mut self, header: &str) -> io::Result<()> {
+        match &self.cli.header_format {
+            Some(template) => writeln!(self.out, "{}", template.replace("{path}", header)),
+            None => {
+                let width = self.header_width;
+                writeln!(self.out, "=== {header:<width$} ===")
+            }
+        }
+    }
+
+    /// Print `doc` for --single: a path comment, then its content, keeping
+    /// the next comment on a line of its own.
+    fn print_inline(&mut self, doc: &Document) -> io::Result<()> {
+        let cli = self.cli;
+        let names: Vec<String> = doc.files.iter().map(|f| self.name_of(f)).collect();
+        let mut header = names.join(", ");
+        if let (true, Some(encoding)) = (cli.show_encoding, doc.encoding) {
+            header.push_str(&encoding_note(encoding));
+        }
+        if let Some(note) = doc.note {
+            header.push_str(&format!(" ({note})"));
+        }
+        if self.mid_line {
+            writeln!(self.out)?;
+        }
+        match &cli.header_format {
+            Some(template) => writeln!(self.out, "{}", template.replace("{path}", &header))?,
+            None => writeln!(self.out, "// --- {header} ---")?,
+        }
+        write!(self.out, "{}", doc.content)?;
+        self.mid_line = !doc.content.is_empty() && !doc.content.ends_with('\n');
+        self.printed += 1;
+        Ok(())
+    }
+
+    /// The --json object for `file`, printed as `doc`, with whichever
+    /// --json-fields were asked for.
+    fn json_entry(&self, file: &FileEntry, doc: &Document) -> Value {
+        let cli = self.cli;
+        let path = display_path(file, cli, &self.cwd, &self.base);
+        let mut entry = Map::new();
+        entry.insert("path".into(), path.display().to_string().into());
+        let metadata = fs::metadata(&file.path).ok();
+        if doc.binary {
+            entry.insert("content".into(), Value::Null);
+            entry.insert(
+                "bytes".into(),
+                metadata.as_ref().map_or(0, |m| m.len()).into(),
+            );
+            entry.insert("skipped".into(), "binary".into());
+        } else {
+            entry.insert("content".into(), doc.content.as_str().into());
+            entry.insert("bytes".into(), doc.content.len().into());
+        }
+        for field in &cli.json_fields {
+            let (name, value) = match field {
+                JsonField::Size => ("size", metadata.as_ref().map(|m| m.len()).into()),
+                JsonField::Lines => (
+                    "lines",
+                    (!doc.binary).then(|| doc.content.lines().count()).into(),
+                ),
+                JsonField::Mtime => (
+                    "mtime",
+                    metadata
+                        .as_ref()
+                        .and_then(|m| m.modified().ok())
+                        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                        .map(|d| d.as_secs())
+                        .into(),
+                ),
+                JsonField::Hash => (
+                    "hash",
+                    fs::read(&file.path)
+                        .ok()
+                        .map(|bytes| format!("sha256:{}", sha256_hex(&bytes)))
+                        .into(),
+                ),
+                JsonField::Lang => {
+                    let lang = match doc.binary {
+                        true => "",
+                        false => language(cli, &file.path, &doc.content),
+                    };
+                    ("lang", (!lang.is_empty()).then_some(lang).into())
+                }
+            };
+            entry.insert(name.into(), value);
+        }
+        Value::Object(entry)
+    }
+
+    /// `file`'s name in a header, or under --anonymize-paths a placeholder
+    /// like `file_01.rs`, the same one each time the file comes up.
+    fn name_of(&mut self, file: &FileEntry) -> String {
+        if !self.cli.anonymize_paths {
+            return header_name(file, self.cli, &self.cwd, &self.base);
+        }
+        let real = self.portable_name(file);
+        let alias = match self.aliases.iter().find(|(r, _)| *r == real) {
+            Some((_, alias)) => alias.clone(),
+            None => {
+                let n = self.aliases.len() + 1;
+                let width = self.total.max(n).to_string().len().max(2);
+                let ext = file
+                    .path
+                    .extension()
+                    .map_or(String::new(), |e| format!(".{}", e.to_string_lossy()));
+                let alias = format!("file_{n:0width$}{ext}");
+                self.aliases.push((real, alias.clone()));
+                alias
+            }
+        };
+        match file.lines {
+            Some(range) => format!("{alias}:{range}"),
+            None => alias,
+        }
+    }
+
+    /// `file`'s display path, normalized and with `/` separators, for
+    /// formats another tool reads back.
+    fn portable_name(&self, file: &FileEntry) -> String {
+        let name = normalize(&display_path(file, self.cli, &self.cwd, &self.base));
+        name.to_string_lossy().replace('\\', "/")
+    }
+
+    /// Print an outline of `files`, ahead of the documents unless it's all
+    /// --count or --list asked for.
+    pub fn tree(&mut self, files: &[FileEntry]) -> io::Result<()> {
+        let paths = self.tree_paths(files);
+        let outline = tree::render(&paths, self.cli.sort_dirs_before_files);
+        // A blank line parts it from the documents, if any follow.
+        if self.cli.count || self.cli.list {
+            write!(self.out, "{outline}")
+        } else {
+            writeln!(self.out, "{outline}")
+        }
+    }
+
+    /// Print the layout of `files` as nested JSON, for --tree-json.
+    pub fn tree_json(&mut self, files: &[FileEntry]) -> io::Result<()> {
+        let paths = self.tree_paths(files);
+        let sizes: Option<Vec<u64>> = self.cli.tree_json_sizes.then(|| {
+            files
+                .iter()
+                .map(|f| fs::metadata(&f.path).map_or(0, |m| m.len()))
+                .collect()
+        });
+        serde_json::to_writer(&mut self.out, &tree::to_json(&paths, sizes.as_deref()))?;
+        writeln!(self.out)
+    }
+
+    fn tree_paths(&self, files: &[FileEntry]) -> Vec<PathBuf> {
+        files
+            .iter()
+            .map(|f| normalize(&display_path(f, self.cli, &self.cwd, &self.base)))
+            .collect()
+    }
+
+    /// Write anything that trails the last document.
+    pub fn finish(mut self) -> io::Result<()> {
+        if self.cli.json {
+            serde_json::to_writer(&mut self.out, &self.json)?;
+            writeln!(self.out)?;
+        }
+        if self.cli.index_footer {
+            if self.mid_line {
+                writeln!(self.out)?;
+            }
+            writeln!(self.out)?;
+            writeln!(self.out, "=== index ===")?;
+            for entry in &self.index {
+                match entry.lines {
+                    Some((start, end)) => writeln!(self.out, "{}: {start}-{end}", entry.name)?,
+                    None => writeln!(self.out, "{}: (empty)", entry.name)?,
+                }
+            }
+        }
+        if self.cli.anonymize_paths {
+            let mapping: String = self
+                .aliases
+                .iter()
+                .map(|(real, alias)| format!("{alias}\t{real}\n"))
+                .collect();
+            match &self.cli.mapping_file {
+                Some(path) => fs::write(path, mapping)?,
+                None => eprint!("{mapping}"),
+            }
+        }
+        if self.cli.stats {
+            let (files, lines, bytes) = self.totals;
+            let noun = if files == 1 { "file" } else { "files" };
+            let mut summary = format!(
+                "{files} {noun}, {} lines, {}",
+                thousands(lines),
+                format_size(bytes as u64)
+            );
+            if self.binaries_omitted > 0 {
+                let noun = match self.binaries_omitted {
+                    1 => "file",
+                    _ => "files",
+                };
+                summary.push_str(&format!(
+                    ", {} binary {noun} omitted",
+                    self.binaries_omitted
+                ));
+            }
+            eprintln!("{summary}");
+            if self.cli.ext_stats {
+                eprint!("{}", self.ext_stats.render());
+            }
+        }
+        self.out.flush()
+    }
+}
+
+/// Files, bytes and lines by extension, for `--ext-stats`.
+#[derive(Default)]
+pub struct ExtStats(BTreeMap<String, ExtRow>);
+
+#[derive(Default)]
+struct ExtRow {
+    files: usize,
+    bytes: u64,
+    lines: usize,
+}
+
+impl ExtStats {
+    /// Count a file of `bytes` bytes and `lines` lines.
+    pub fn add(&mut self, path: &Path, bytes: u64, lines: usize) {
+        let ext = match path.extension() {
+            Some(ext) => format!(".{}", ext.to_string_lossy()),
+            None => "(none)".to_string(),
+        };
+        let row = self.0.entry(ext).or_default();
+        row.files += 1;
+        row.bytes += bytes;
+        row.lines += lines;
+    }
+
+    /// An aligned table, largest extension first, ending with a total.
+    pub fn render(&self) -> String {
+        let mut rows: Vec<(&str, &ExtRow)> = self.0.iter().map(|(e, r)| (e.as_str(), r)).collect();
+        // Stable, so equal sizes stay in extension order.
+        rows.sort_by_key(|(_, r)| std::cmp::Reverse(r.bytes));
+        let total = ExtRow {
+            files: rows.iter().map(|(_, r)| r.files).sum(),
+            bytes: rows.iter().map(|(_, r)| r.bytes).sum(),
+            lines: rows.iter().map(|(_, r)| r.lines).sum(),
+        };
+        rows.push(("TOTAL", &total));
+
+        let width = rows
+            .iter()
+            .map(|(ext, _)| ext.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("EXT".len());
+        let mut out = format!(
+            "{:<width$}  {:>6}  {:>10}  {:>8}\n",
+            "EXT", "FILES", "BYTES", "LINES"
+        );
+        for (ext, row) in &rows {
+            out.push_str(&format!(
+                "{:<width$}  {:>6}  {:>10}  {:>8}\n",
+                ext, row.files, row.bytes, row.lines
+            ));
+        }
+        out
+    }
+}
+
+/// ` (utf-16le)`: how --show-encoding names `encoding` in a header.
+fn encoding_note(encoding: &'static Encoding) -> String {
+    format!(" ({})", encoding.name().to_ascii_lowercase())
+}
+
+/// `content` with its trailing newlines, none or several, made exactly one
+/// (kept as `\r\n` if that's what it ended with). Empty content stays empty.
+fn with_one_newline(content: &str) -> String {
+    if content.is_empty() {
+        return String::new();
+    }
+    let body = content.trim_end_matches(['\r', '\n']);
+    let eol = match content[body.len()..].starts_with("\r\n") {
+        true => "\r\n",
+        false => "\n",
+    };
+    format!("{body}{eol}")
+}
+
+/// `n` with commas between groups of three digits, e.g. `1,204`.
+fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// The display name for `file` in a header, with any requested annotations.
+pub fn header_name(file: &FileEntry, cli: &Cli, cwd: &Path, base: &Path) -> String {
+    let path = &file.path;
+    let rel = display_path(file, cli, cwd, base);
+    let mut name = match cli.strip_components {
+        Some(n) => strip_components(&rel, n),
+        None => rel,
+    }
+    .display()
+    .to_string();
+    if let Some(range) = file.lines {
+        name.push_str(&format!(":{range}"));
+    }
+    if cli.show_symlinks {
+        if let Ok(target) = fs::read_link(path) {
+            name.push_str(&format!(" -> {}", target.display()));
+        }
+    }
+    if cli.show_mode {
+        if let Some(mode) = file_mode(path) {
+            name.push_str(&format!(" ({mode:04o})"));
+        }
+    }
+    name
+}
+
+/// `file`'s path relative to whatever `--relative-to-input` or
+/// `--smart-root` asks for, defaulting to `cwd`.
+fn display_path(file: &FileEntry, cli: &Cli, cwd: &Path, base: &Path) -> PathBuf {
+    let path = &file.path;
+    match &file.root {
+        Some(root) if cli.relative_to_input => path.strip_prefix(root).unwrap_or(path).into(),
+        _ if base != cwd => {
+            let absolute = normalize(&cwd.join(path));
+            relative_to(&absolute, base).unwrap_or(absolute)
+        }
+        _ => relative_to(path, cwd).unwrap_or_else(|| path.clone()),
+    }
+}
+
+/// `path` relative to `dir` (a canonical directory, like the current one),
+/// also when `path` reaches it through a symlinked directory. The file name
+/// itself is never resolved, so a symlinked file keeps its own name.
+fn relative_to(path: &Path, dir: &Path) -> Option<PathBuf> {
+    if let Ok(rel) = path.strip_prefix(dir) {
+        return Some(rel.into());
+    }
+    if !path.is_absolute() {
+        return None;
+    }
+    let parent = fs::canonicalize(path.parent()?).ok()?;
+    let resolved = parent.join(path.file_name()?);
+    resolved.strip_prefix(dir).ok().map(PathBuf::from)
+}
+
+/// `text` safe to put in HTML, as the --collapsible summary is.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// A backtick fence longer than any backtick run in `content`, so the
+/// content can't close it early.
+fn fence_for(content: &str) -> String {
+    let longest = content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+/// The language of `path`, from --content-type-map, its extension or else
+/// its shebang, as a Markdown info string; empty when unknown.
+fn language<'a>(cli: &'a Cli, path: &Path, content: &str) -> &'a str {
+    if let Some(mapped) = ContentType::lookup(cli, path) {
+        return &mapped.lang;
+    }
+    match fence_language(path) {
+        "" => shebang_language(content),
+        lang => lang,
+    }
+}
+
+/// The Markdown info string for `path`'s extension; empty when unknown.
+fn fence_language(path: &Path) -> &'static str {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    match ext.to_ascii_lowercase().as_str() {
+        "rs" => "rust",
+        "py" | "pyi" => "python",
+        "go" => "go",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" => "cpp",
+        "js" | "mjs" | "cjs" => "javascript",
+        "ts" => "typescript",
+        "tsx" => "tsx",
+        "jsx" => "jsx",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "json" => "json",
+        "toml" => "toml",
+        "yaml" | "yml" => "yaml",
+        "md" | "markdown" => "markdown",
+        "sh" | "bash" => "bash",
+        "rb" => "ruby",
+        "java" => "java",
+        "kt" => "kotlin",
+        "swift" => "swift",
+        "sql" => "sql",
+        "xml" => "xml",
+        "vue" => "vue",
+        "svelte" => "svelte",
+        "astro" => "astro",
+        _ => "",
+    }
+}
+
+/// The Markdown info string for a script's `#!` interpreter, e.g. `python`
+/// for `#!/usr/bin/env python3`; empty without a recognized shebang.
+fn shebang_language(content: &str) -> &'static str {
+    let Some(shebang) = content.lines().next().and_then(|l| l.strip_prefix("#!")) else {
+        return "";
+    };
+    let mut words = shebang.split_whitespace();
+    let mut program = words.next().unwrap_or("").rsplit('/').next().unwrap_or("");
+    if program == "env" {
+        // Skip `env`'s own options, as in `#!/usr/bin/env -S node --flag`.
+        program = words.find(|w| !w.starts_with('-')).unwrap_or("");
+    }
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    match name {
+        "python" | "pypy" => "python",
+        "bash" | "sh" | "dash" | "ksh" => "bash",
+        "zsh" => "zsh",
+        "fish" => "fish",
+        "node" | "nodejs" => "javascript",
+        "deno" | "ts-node" => "typescript",
+        "ruby" => "ruby",
+        "perl" => "perl",
+        "php" => "php",
+        "lua" => "lua",
+        _ => "",
+    }
+}
+
+/// Drop the first `n` components of `path`, like `tar --strip-components`.
+/// Paths too short to strip that far keep just their file name.
+fn strip_components(path: &Path, n: usize) -> PathBuf {
+    let normalized = normalize(path);
+    let parts: Vec<_> = normalized.components().collect();
+    if parts.len() <= n {
+        return path.file_name().map(PathBuf::from).unwrap_or_default();
+    }
+    parts[n..].iter().collect()
+}
+
+/// Permission bits of `path`, where the platform has them.
+#[cfg(unix)]
+fn file_mode(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path)
+        .ok()
+        .map(|m| m.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_path: &Path) -> Option<u32> {
+    None
+}
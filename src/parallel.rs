@@ -0,0 +1,104 @@
+// src/parallel.rs
+//! Reading files on worker threads while printing them in order.
+
+use std::collections::HashMap;
+use std::sync::{mpsc, Condvar, Mutex};
+
+/// Results sent from parallel workers as `(index, result)`, handed out in
+/// index order.
+pub struct InOrder<T> {
+    rx: mpsc::Receiver<(usize, T)>,
+    early: HashMap<usize, T>,
+    next: usize,
+}
+
+impl<T> InOrder<T> {
+    pub fn new(rx: mpsc::Receiver<(usize, T)>) -> Self {
+        InOrder {
+            rx,
+            early: HashMap::new(),
+            next: 0,
+        }
+    }
+}
+
+impl<T> Iterator for InOrder<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let item = match self.early.remove(&self.next) {
+            Some(item) => item,
+            None => loop {
+                let (i, item) = self.rx.recv().ok()?;
+                if i == self.next {
+                    break item;
+                }
+                self.early.insert(i, item);
+            },
+        };
+        self.next += 1;
+        Some(item)
+    }
+}
+
+/// How many bytes of file content may be read but not yet printed, for
+/// `--read-buffer-cap`. Without a cap, nothing ever waits.
+pub struct ReadBudget {
+    cap: Option<u64>,
+    state: Mutex<BudgetState>,
+    changed: Condvar,
+}
+
+struct BudgetState {
+    held: u64,
+    closed: bool,
+}
+
+impl ReadBudget {
+    pub fn new(cap: Option<u64>) -> Self {
+        ReadBudget {
+            cap,
+            state: Mutex::new(BudgetState {
+                held: 0,
+                closed: false,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Wait until `len` more bytes fit under the cap, then hold them. A file
+    /// is always let through when nothing else is held, so one bigger than
+    /// the cap still gets read. Returns `false` once the budget is closed.
+    pub fn acquire(&self, len: u64) -> bool {
+        let mut state = self.state.lock().expect("budget lock poisoned");
+        if let Some(cap) = self.cap {
+            while !state.closed && state.held > 0 && state.held + len > cap {
+                state = self.changed.wait(state).expect("budget lock poisoned");
+            }
+        }
+        state.held += len;
+        !state.closed
+    }
+
+    /// Give back `len` bytes once their file has been taken for printing.
+    pub fn release(&self, len: u64) {
+        let mut state = self.state.lock().expect("budget lock poisoned");
+        state.held = state.held.saturating_sub(len);
+        self.changed.notify_all();
+    }
+
+    /// Stop handing out bytes, waking anyone waiting for them.
+    pub fn close(&self) {
+        self.state.lock().expect("budget lock poisoned").closed = true;
+        self.changed.notify_all();
+    }
+}
+
+/// Closes its budget when dropped, however the printing loop stops.
+pub struct CloseOnDrop<'a>(pub &'a ReadBudget);
+
+impl Drop for CloseOnDrop<'_> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
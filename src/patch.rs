@@ -0,0 +1,23 @@
+// src/patch.rs
+//! Formatting files as `git apply`-compatible new-file patches.
+
+/// A `diff --git` block that creates `name` with `content`.
+pub fn new_file(name: &str, content: &str, executable: bool) -> String {
+    let mode = if executable { "100755" } else { "100644" };
+    let mut out = format!("diff --git a/{name} b/{name}\nnew file mode {mode}\n");
+    if content.is_empty() {
+        return out;
+    }
+    let count = content.split_inclusive('\n').count();
+    out.push_str(&format!(
+        "--- /dev/null\n+++ b/{name}\n@@ -0,0 +1,{count} @@\n"
+    ));
+    for line in content.split_inclusive('\n') {
+        out.push('+');
+        out.push_str(line);
+    }
+    if !content.ends_with('\n') {
+        out.push_str("\n\\ No newline at end of file\n");
+    }
+    out
+}
@@ -0,0 +1,27 @@
+// src/progress.rs
+//! `--progress`: a progress bar on stderr while files are read.
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// A bar counting bytes read, with throughput and an ETA when the `total`
+/// is known, or a plain spinner when it isn't (a FIFO or a file that
+/// couldn't be stat-ed is among them). It draws nothing unless `shown` and
+/// stderr is a terminal.
+pub fn read_bar(shown: bool, total: Option<u64>) -> ProgressBar {
+    if !shown {
+        return ProgressBar::hidden();
+    }
+    match total {
+        Some(total) => ProgressBar::new(total).with_style(
+            ProgressStyle::with_template(
+                "{spinner} reading [{bar:30}] {bytes}/{total_bytes} ({bytes_per_sec}, ETA {eta})",
+            )
+            .expect("valid template")
+            .progress_chars("=> "),
+        ),
+        None => ProgressBar::new_spinner().with_style(
+            ProgressStyle::with_template("{spinner} reading {bytes} ({bytes_per_sec})")
+                .expect("valid template"),
+        ),
+    }
+}
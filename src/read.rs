@@ -0,0 +1,165 @@
+// src/read.rs
+//! Reading file contents as text.
+
+use std::fs::{File, FileType};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use encoding_rs::Encoding;
+
+/// How much of a file is searched for a NUL byte when sniffing for binary.
+const SNIFF_LEN: usize = 8192;
+
+/// How long a FIFO, socket or device is read before giving up on it.
+const SPECIAL_READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The most read from a FIFO, socket or device, since some (`/dev/zero`)
+/// never end.
+const SPECIAL_READ_CAP: usize = 1 << 20;
+
+/// Read a file's content as UTF-8 text, or `None` if it looks binary.
+///
+/// A leading byte-order mark selects the decoder (UTF-8, UTF-16 LE/BE) and
+/// is stripped. Files without one are binary if they have a NUL byte in
+/// their first 8 KiB or aren't valid UTF-8.
+///
+/// If reading fails partway through, the bytes read so far are kept and a
+/// `[read error: ...]` marker line is appended, rather than losing the file.
+/// FIFOs, sockets and devices get the same marker if they time out.
+///
+/// Paths longer than Windows' legacy `MAX_PATH` need no special handling:
+/// `std::fs` adds the `\\?\` prefix itself when a path needs it.
+pub fn read_file(path: &Path) -> anyhow::Result<Option<String>> {
+    Ok(read_decoded(path)?.map(|(text, _)| text))
+}
+
+/// `read_file`, along with the encoding the text was decoded from: UTF-8
+/// unless a byte-order mark said otherwise.
+pub fn read_decoded(path: &Path) -> anyhow::Result<Option<(String, &'static Encoding)>> {
+    #[cfg(debug_assertions)]
+    crate::test_hooks::maybe_stall(path);
+
+    let (bytes, read_error) = read_bytes(path)?;
+    let Some((mut text, encoding)) = decode(&bytes, read_error.is_some()) else {
+        return Ok(None);
+    };
+    if let Some(err) = read_error {
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str(&format!("[read error: {err}]\n"));
+    }
+    Ok(Some((text, encoding)))
+}
+
+/// Read a file that `read_file` found binary, replacing invalid UTF-8 with
+/// `U+FFFD` so it can still be printed.
+pub fn read_lossy(path: &Path) -> anyhow::Result<String> {
+    match read_bytes(path)? {
+        (bytes, None) => Ok(String::from_utf8_lossy(&bytes).into_owned()),
+        (_, Some(err)) => Err(err.into()),
+    }
+}
+
+/// Open `path` to be streamed line by line rather than read whole, or
+/// `None` if its start says it needs `read_file`: a byte-order mark to
+/// decode, or a NUL byte marking it binary.
+pub fn open_streamable(path: &Path) -> io::Result<Option<BufReader<File>>> {
+    let mut reader = BufReader::with_capacity(64 * 1024, File::open(path)?);
+    let head = reader.fill_buf()?;
+    let plain =
+        Encoding::for_bom(head).is_none() && !head[..head.len().min(SNIFF_LEN)].contains(&0);
+    Ok(plain.then_some(reader))
+}
+
+/// Is this a FIFO, socket or device, rather than a file, directory or
+/// symlink? Reading one can block forever.
+pub fn is_special(file_type: FileType) -> bool {
+    !file_type.is_file() && !file_type.is_dir() && !file_type.is_symlink()
+}
+
+/// The bytes of `path`, along with the error that cut reading short, if
+/// any. Special files are read with a timeout rather than trusted to end.
+fn read_bytes(path: &Path) -> anyhow::Result<(Vec<u8>, Option<io::Error>)> {
+    if std::fs::metadata(path).is_ok_and(|m| is_special(m.file_type())) {
+        return Ok(read_special(path));
+    }
+    let file = File::open(path)?;
+    #[cfg(debug_assertions)]
+    let mut reader = crate::test_hooks::faulty_reader(path, file);
+    #[cfg(not(debug_assertions))]
+    let mut reader = file;
+
+    let mut bytes = Vec::new();
+    let read_error = reader.read_to_end(&mut bytes).err();
+    Ok((bytes, read_error))
+}
+
+/// Read a special file on its own thread, keeping whatever arrives within
+/// `SPECIAL_READ_TIMEOUT` (up to `SPECIAL_READ_CAP` bytes). Even opening a
+/// FIFO blocks until it has a writer, so that happens on the thread too; a
+/// reader that never finishes is left blocked until the process exits.
+fn read_special(path: &Path) -> (Vec<u8>, Option<io::Error>) {
+    let (tx, rx) = mpsc::channel();
+    let owned = path.to_path_buf();
+    std::thread::spawn(move || {
+        let mut file = match File::open(&owned) {
+            Ok(file) => file,
+            Err(err) => return drop(tx.send(Err(err))),
+        };
+        let mut chunk = vec![0; SNIFF_LEN];
+        loop {
+            let sent = match file.read(&mut chunk) {
+                Ok(0) => return,
+                Ok(n) => tx.send(Ok(chunk[..n].to_vec())),
+                Err(err) => return drop(tx.send(Err(err))),
+            };
+            if sent.is_err() {
+                return;
+            }
+        }
+    });
+
+    let deadline = Instant::now() + SPECIAL_READ_TIMEOUT;
+    let mut bytes = Vec::new();
+    while bytes.len() < SPECIAL_READ_CAP {
+        let wait = deadline.saturating_duration_since(Instant::now());
+        match rx.recv_timeout(wait) {
+            Ok(Ok(chunk)) => bytes.extend_from_slice(&chunk),
+            Ok(Err(err)) => return (bytes, Some(err)),
+            Err(mpsc::RecvTimeoutError::Disconnected) => return (bytes, None),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                let secs = SPECIAL_READ_TIMEOUT.as_secs();
+                let err =
+                    io::Error::new(io::ErrorKind::TimedOut, format!("timed out after {secs}s"));
+                return (bytes, Some(err));
+            }
+        }
+    }
+    bytes.truncate(SPECIAL_READ_CAP);
+    let err = io::Error::other(format!("stopped after {} KiB", SPECIAL_READ_CAP >> 10));
+    (bytes, Some(err))
+}
+
+/// Decode `bytes` per their BOM, or as UTF-8, returning `None` for binary.
+/// A `truncated` read may end mid-character; that tail is dropped instead of
+/// failing the decode.
+fn decode(bytes: &[u8], truncated: bool) -> Option<(String, &'static Encoding)> {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+        return Some((text.into_owned(), encoding));
+    }
+    if bytes[..bytes.len().min(SNIFF_LEN)].contains(&0) {
+        return None;
+    }
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(e) if truncated && e.error_len().is_none() => {
+            String::from_utf8_lossy(&bytes[..e.valid_up_to()]).into_owned()
+        }
+        Err(_) => return None,
+    };
+    Some((text, encoding_rs::UTF_8))
+}
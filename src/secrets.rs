@@ -0,0 +1,83 @@
+// src/secrets.rs
+//! `--scan-secrets`: spotting content that looks like credentials.
+
+use regex::Regex;
+
+/// Fewest characters a token needs before its entropy is worth measuring.
+const MIN_TOKEN_LEN: usize = 32;
+
+/// Bits of entropy per character above which a token looks random; hex
+/// digests top out at 4, so commit hashes and checksums stay below it.
+const ENTROPY_THRESHOLD: f64 = 4.5;
+
+/// The built-in rules, each a name and the pattern it looks for.
+pub struct Scanner {
+    rules: Vec<(&'static str, Regex)>,
+    token: Regex,
+}
+
+impl Scanner {
+    pub fn new() -> Self {
+        let rules = [
+            ("aws-access-key-id", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+            (
+                "private-key",
+                r"-----BEGIN (?:(?:RSA|DSA|EC|OPENSSH|ENCRYPTED|PGP) )?PRIVATE KEY(?: BLOCK)?-----",
+            ),
+            (
+                "github-token",
+                r"\b(?:gh[pousr]_[A-Za-z0-9]{36,}|github_pat_\w{22,})\b",
+            ),
+            ("slack-token", r"\bxox[abposr]-[A-Za-z0-9-]{10,}"),
+        ];
+        Scanner {
+            rules: rules
+                .into_iter()
+                .map(|(name, re)| (name, Regex::new(re).expect("valid regex")))
+                .collect(),
+            token: Regex::new(&format!(r"[A-Za-z0-9+/=_-]{{{MIN_TOKEN_LEN},}}"))
+                .expect("valid regex"),
+        }
+    }
+
+    /// The names of the rules `text` trips, in rule order, each once.
+    pub fn scan(&self, text: &str) -> Vec<&'static str> {
+        let mut hits: Vec<&'static str> = self
+            .rules
+            .iter()
+            .filter(|(_, re)| re.is_match(text))
+            .map(|(name, _)| *name)
+            .collect();
+        if self.token.find_iter(text).any(|m| looks_random(m.as_str())) {
+            hits.push("high-entropy-token");
+        }
+        hits
+    }
+}
+
+/// Does `token` mix cases and digits with enough entropy to be a key
+/// rather than an identifier or a path?
+fn looks_random(token: &str) -> bool {
+    let has = |f: fn(&char) -> bool| token.chars().any(|c| f(&c));
+    has(char::is_ascii_uppercase)
+        && has(char::is_ascii_lowercase)
+        && has(char::is_ascii_digit)
+        && entropy(token) > ENTROPY_THRESHOLD
+}
+
+/// Shannon entropy of `s`, in bits per character.
+fn entropy(s: &str) -> f64 {
+    let mut counts = [0usize; 256];
+    for b in s.bytes() {
+        counts[b as usize] += 1;
+    }
+    let len = s.len() as f64;
+    counts
+        .iter()
+        .filter(|&&n| n > 0)
+        .map(|&n| {
+            let p = n as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
@@ -0,0 +1,56 @@
+// src/seen.rs
+//! The `--seen-cache` file: content hashes of files shared on earlier runs.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::checksum::sha256_hex;
+
+/// A set of content hashes persisted as one hex digest per line.
+pub struct SeenCache {
+    path: PathBuf,
+    hashes: HashSet<String>,
+}
+
+impl SeenCache {
+    /// Load the cache at `path`; a missing file (or `reset`) starts empty.
+    pub fn load(path: &Path, reset: bool) -> anyhow::Result<Self> {
+        let hashes = match fs::read_to_string(path) {
+            Ok(_) if reset => HashSet::new(),
+            Ok(text) => text
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(String::from)
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashSet::new(),
+            Err(e) => anyhow::bail!("cannot read seen cache `{}`: {e}", path.display()),
+        };
+        Ok(SeenCache {
+            path: path.to_path_buf(),
+            hashes,
+        })
+    }
+
+    /// Was `content` shared on an earlier run?
+    pub fn contains(&self, content: &str) -> bool {
+        self.hashes.contains(&sha256_hex(content.as_bytes()))
+    }
+
+    /// Record `content` as shared.
+    pub fn insert(&mut self, content: &str) {
+        self.hashes.insert(sha256_hex(content.as_bytes()));
+    }
+
+    /// Write the cache back out, sorted so the file diffs cleanly.
+    pub fn save(self) -> anyhow::Result<()> {
+        let mut hashes: Vec<String> = self.hashes.into_iter().collect();
+        hashes.sort();
+        let mut text = hashes.join("\n");
+        text.push('\n');
+        fs::write(&self.path, text)
+            .map_err(|e| anyhow::anyhow!("cannot write seen cache `{}`: {e}", self.path.display()))
+    }
+}
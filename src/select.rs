@@ -0,0 +1,51 @@
+// src/select.rs
+//! `--interactive`: let the user tick which resolved files to print.
+
+use std::io::{self, IsTerminal};
+use std::path::Path;
+
+use dialoguer::MultiSelect;
+
+use crate::FileEntry;
+
+/// Present a checklist of `files` (all ticked) on the terminal and keep the
+/// ones left ticked. Without a terminal there is no one to ask, so every
+/// file is kept and a note goes to stderr.
+pub fn choose(files: Vec<FileEntry>, cwd: &Path) -> anyhow::Result<Vec<FileEntry>> {
+    if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        eprintln!(
+            "note: --interactive needs a terminal; printing all {} files",
+            files.len()
+        );
+        return Ok(files);
+    }
+    if files.is_empty() {
+        return Ok(files);
+    }
+
+    let items: Vec<(String, bool)> = files
+        .iter()
+        .map(|f| {
+            (
+                f.path
+                    .strip_prefix(cwd)
+                    .unwrap_or(&f.path)
+                    .display()
+                    .to_string(),
+                true,
+            )
+        })
+        .collect();
+    let picked = MultiSelect::new()
+        .with_prompt("Select files to print (space toggles, enter confirms)")
+        .items_checked(&items)
+        .interact_opt()?
+        .ok_or_else(|| anyhow::anyhow!("selection cancelled"))?;
+
+    Ok(files
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| picked.contains(i))
+        .map(|(_, f)| f)
+        .collect())
+}
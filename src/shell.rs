@@ -0,0 +1,69 @@
+// src/shell.rs
+//! Shell-style expansion of a leading `~` and of `$VAR` / `${VAR}` in
+//! inputs.
+
+use std::path::Path;
+
+/// Expand `~` at the start of `input` to the home directory, and every
+/// `$VAR` or `${VAR}` to that environment variable. A variable that isn't
+/// set is an error rather than an empty string. A `$` not followed by a
+/// name (a letter or `_`, then letters, digits and `_`) is kept as is, and
+/// so is one escaped as `\$`. An input that names an existing path as
+/// written is left alone unless the expansion names one too, so a file
+/// called `price$list.txt` can still be given as it is.
+pub fn expand(input: &str) -> anyhow::Result<String> {
+    let expanded = expand_unchecked(input);
+    let exists = |path: &str| Path::new(path).symlink_metadata().is_ok();
+    if exists(input) && !expanded.as_deref().is_ok_and(exists) {
+        return Ok(input.to_string());
+    }
+    expanded
+}
+
+fn expand_unchecked(input: &str) -> anyhow::Result<String> {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+    if let Some(after) = rest.strip_prefix('~') {
+        if after.is_empty() || after.starts_with('/') || after.starts_with('\\') {
+            let home = dirs::home_dir()
+                .ok_or_else(|| anyhow::anyhow!("cannot find the home directory for `{input}`"))?;
+            out.push_str(&home.to_string_lossy());
+            rest = after;
+        }
+    }
+    while let Some(dollar) = rest.find('$') {
+        if rest[..dollar].ends_with('\\') {
+            out.push_str(&rest[..dollar - 1]);
+            out.push('$');
+            rest = &rest[dollar + 1..];
+            continue;
+        }
+        out.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, remaining) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(close) => (&braced[..close], &braced[close + 1..]),
+                None => anyhow::bail!("unterminated `${{` in `{input}`"),
+            },
+            None if after.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') => {
+                let len = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..len], &after[len..])
+            }
+            None => ("", after),
+        };
+        if name.is_empty() {
+            out.push('$');
+            rest = after;
+            continue;
+        }
+        let value = std::env::var(name).map_err(|_| {
+            anyhow::anyhow!("environment variable `{name}` is not set (in `{input}`)")
+        })?;
+        out.push_str(&value);
+        rest = remaining;
+    }
+    out.push_str(rest);
+    Ok(out)
+}
@@ -0,0 +1,111 @@
+// src/state.rs
+//! The `--state-file`: each file's content hash as of the last run, so the
+//! next one can print just what's new or changed.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::checksum::sha256_hex;
+use crate::lines::LineRange;
+
+/// What a hash is kept for: a file, or the lines of it a `path:START-END`
+/// input selected.
+pub type Entry = (PathBuf, Option<LineRange>);
+
+/// How a file compares with the last run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    New,
+    Changed,
+    Same,
+}
+
+impl Change {
+    /// The note for a header, if the file is printed at all.
+    pub fn note(self) -> Option<&'static str> {
+        match self {
+            Change::New => Some("new"),
+            Change::Changed => Some("changed"),
+            Change::Same => None,
+        }
+    }
+}
+
+/// Content hashes by absolute path (and line range), persisted as
+/// `<hex digest>\t<path>[:START-END]` lines.
+pub struct RunState {
+    path: PathBuf,
+    hashes: BTreeMap<Entry, String>,
+}
+
+impl RunState {
+    /// Load the state at `path`; a missing file starts empty.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let hashes = match fs::read_to_string(path) {
+            Ok(text) => text
+                .lines()
+                .filter_map(|line| line.split_once('\t'))
+                .map(|(hash, file)| {
+                    let (file, lines) = LineRange::split(file).unwrap_or((file, None));
+                    ((PathBuf::from(file), lines), hash.to_string())
+                })
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => anyhow::bail!("cannot read state file `{}`: {e}", path.display()),
+        };
+        Ok(RunState {
+            path: path.to_path_buf(),
+            hashes,
+        })
+    }
+
+    /// Forget the entries recorded last time that aren't among `present`,
+    /// returning them in path order.
+    pub fn take_removed(&mut self, present: &HashSet<Entry>) -> Vec<Entry> {
+        let removed: Vec<Entry> = self
+            .hashes
+            .keys()
+            .filter(|p| !present.contains(*p))
+            .cloned()
+            .collect();
+        for path in &removed {
+            self.hashes.remove(path);
+        }
+        removed
+    }
+
+    /// How `content` compares with what was recorded for `entry`, and its
+    /// hash, for [`record`](Self::record) once it has been printed. Nothing
+    /// is recorded before then, so that a file left out by a later filter
+    /// or budget still counts as new next time.
+    pub fn compare(&self, entry: &Entry, content: &str) -> (Change, String) {
+        let hash = sha256_hex(content.as_bytes());
+        let change = match self.hashes.get(entry) {
+            None => Change::New,
+            Some(old) if *old == hash => Change::Same,
+            Some(_) => Change::Changed,
+        };
+        (change, hash)
+    }
+
+    /// Record `hash` as the current state of `entry`.
+    pub fn record(&mut self, entry: Entry, hash: String) {
+        self.hashes.insert(entry, hash);
+    }
+
+    /// Write the state back out.
+    pub fn save(self) -> anyhow::Result<()> {
+        let text: String = self
+            .hashes
+            .iter()
+            .map(|((file, lines), hash)| match lines {
+                Some(lines) => format!("{hash}\t{}:{lines}\n", file.display()),
+                None => format!("{hash}\t{}\n", file.display()),
+            })
+            .collect();
+        fs::write(&self.path, text)
+            .map_err(|e| anyhow::anyhow!("cannot write state file `{}`: {e}", self.path.display()))
+    }
+}
@@ -0,0 +1,83 @@
+// src/symbol.rs
+//! `--symbol`: finding where a named item is defined.
+
+use syn::spanned::Spanned;
+use syn::{ImplItem, Item, TraitItem};
+
+use crate::lines::LineRange;
+
+/// The lines of every item named `name` in the Rust source `content`:
+/// functions, types, traits, consts, statics, modules and `macro_rules!`
+/// macros, along with those nested in inline modules, `impl` blocks and
+/// traits. Each range starts at the item's first attribute or doc comment.
+pub fn find_rust(content: &str, name: &str) -> syn::Result<Vec<LineRange>> {
+    let file = syn::parse_file(content)?;
+    let mut found = Vec::new();
+    visit_items(&file.items, name, &mut found);
+    Ok(found)
+}
+
+fn visit_items(items: &[Item], name: &str, found: &mut Vec<LineRange>) {
+    for item in items {
+        let ident = match item {
+            Item::Const(i) => Some(&i.ident),
+            Item::Enum(i) => Some(&i.ident),
+            Item::Fn(i) => Some(&i.sig.ident),
+            Item::Macro(i) => i.ident.as_ref(),
+            Item::Mod(i) => Some(&i.ident),
+            Item::Static(i) => Some(&i.ident),
+            Item::Struct(i) => Some(&i.ident),
+            Item::Trait(i) => Some(&i.ident),
+            Item::TraitAlias(i) => Some(&i.ident),
+            Item::Type(i) => Some(&i.ident),
+            Item::Union(i) => Some(&i.ident),
+            _ => None,
+        };
+        if ident.is_some_and(|i| i == name) {
+            found.push(lines_of(item));
+        }
+        match item {
+            Item::Mod(m) => {
+                if let Some((_, items)) = &m.content {
+                    visit_items(items, name, found);
+                }
+            }
+            Item::Impl(i) => {
+                for item in &i.items {
+                    let ident = match item {
+                        ImplItem::Const(i) => Some(&i.ident),
+                        ImplItem::Fn(i) => Some(&i.sig.ident),
+                        ImplItem::Type(i) => Some(&i.ident),
+                        _ => None,
+                    };
+                    if ident.is_some_and(|i| i == name) {
+                        found.push(lines_of(item));
+                    }
+                }
+            }
+            Item::Trait(t) => {
+                for item in &t.items {
+                    let ident = match item {
+                        TraitItem::Const(i) => Some(&i.ident),
+                        TraitItem::Fn(i) => Some(&i.sig.ident),
+                        TraitItem::Type(i) => Some(&i.ident),
+                        _ => None,
+                    };
+                    if ident.is_some_and(|i| i == name) {
+                        found.push(lines_of(item));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The 1-based lines `node` spans.
+pub fn lines_of(node: &impl Spanned) -> LineRange {
+    let span = node.span();
+    LineRange {
+        start: span.start().line,
+        end: Some(span.end().line),
+    }
+}
@@ -0,0 +1,58 @@
+// src/test_hooks.rs
+//! Fault injection and environment overrides for the integration tests.
+//! Only compiled into debug builds, and every hook is inert unless its
+//! `FPR_TEST_*` variable is set.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::Duration;
+
+/// Block for a long time when reading a file whose name equals
+/// `FPR_TEST_STALL_ON`, simulating a hung network mount.
+pub fn maybe_stall(path: &Path) {
+    let Ok(name) = std::env::var("FPR_TEST_STALL_ON") else {
+        return;
+    };
+    if path.file_name().is_some_and(|n| n == name.as_str()) {
+        std::thread::sleep(Duration::from_secs(60));
+    }
+}
+
+/// Whether to treat the process as attached to a terminal, for --edit,
+/// when `FPR_TEST_TTY` is set.
+pub fn assume_terminal() -> bool {
+    std::env::var_os("FPR_TEST_TTY").is_some()
+}
+
+/// Wrap `file` so reads fail after `N` bytes when `FPR_TEST_FAIL_READ` is
+/// `<file name>:<N>`, simulating a flaky mount.
+pub fn faulty_reader(path: &Path, file: File) -> Box<dyn Read> {
+    let Ok(spec) = std::env::var("FPR_TEST_FAIL_READ") else {
+        return Box::new(file);
+    };
+    match spec.rsplit_once(':') {
+        Some((name, limit)) if path.file_name().is_some_and(|n| n == name) => Box::new(FailAfter {
+            inner: file,
+            remaining: limit.parse().unwrap_or(0),
+        }),
+        _ => Box::new(file),
+    }
+}
+
+struct FailAfter<R> {
+    inner: R,
+    remaining: usize,
+}
+
+impl<R: Read> Read for FailAfter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            return Err(io::Error::other("injected failure"));
+        }
+        let len = buf.len().min(self.remaining);
+        let n = self.inner.read(&mut buf[..len])?;
+        self.remaining -= n;
+        Ok(n)
+    }
+}
@@ -0,0 +1,151 @@
+// src/testcode.rs
+//! `--exclude-tests` and `--strip-test-modules`: telling test code apart
+//! from the code under test.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use syn::Item;
+
+use crate::lines::LineRange;
+use crate::symbol::lines_of;
+
+/// Directories whose contents are taken to be tests.
+const TEST_DIRS: &[&str] = &["tests", "test", "__tests__", "spec", "specs"];
+
+/// Is the file at `path` test code, going by its path or, for Rust, by a
+/// `#![cfg(test)]` at the top? Only the directories below `root`, the
+/// directory input it was found under, count, or below `cwd` for a file
+/// named directly or by a glob: a project kept somewhere like
+/// `~/work/tests/` isn't all tests.
+pub fn is_test_file(path: &Path, cwd: &Path, root: Option<&Path>) -> bool {
+    let below = |base: &Path| {
+        let rel = lexical(&cwd.join(path))
+            .strip_prefix(lexical(&cwd.join(base)))
+            .ok()?
+            .to_path_buf();
+        (!rel.components().any(|c| c == Component::ParentDir)).then_some(rel)
+    };
+    let judged = root
+        .and_then(below)
+        .or_else(|| below(cwd))
+        .or_else(|| path.file_name().map(PathBuf::from))
+        .unwrap_or_default();
+    is_test_path(&judged)
+        || path.extension().is_some_and(|e| e == "rs")
+            && fs::read_to_string(path).is_ok_and(|c| is_test_only_rust(&c))
+}
+
+/// `path` without its `.` components.
+fn lexical(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| *c != Component::CurDir)
+        .collect()
+}
+
+/// Does `path` look like a test by name alone? That's anything under a
+/// `tests/`, `test/`, `__tests__/` or `spec(s)/` directory, a file named
+/// like `*_test.*`, `test_*.*`, `*.test.*`, `*_spec.*` or `*.spec.*`, and
+/// Rust's `tests.rs` and pytest's `conftest.py`.
+fn is_test_path(path: &Path) -> bool {
+    let in_test_dir = path.parent().is_some_and(|dir| {
+        dir.components().any(|c| match c {
+            Component::Normal(name) => name.to_str().is_some_and(|n| TEST_DIRS.contains(&n)),
+            _ => false,
+        })
+    });
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return in_test_dir;
+    };
+    let stem = name.split('.').next().unwrap_or(name);
+    let inner_exts = name.split('.').skip(1).collect::<Vec<_>>();
+    in_test_dir
+        || matches!(name, "tests.rs" | "conftest.py")
+        || stem.ends_with("_test")
+        || stem.ends_with("_spec")
+        || stem.starts_with("test_")
+        || inner_exts.len() > 1
+            && inner_exts[..inner_exts.len() - 1]
+                .iter()
+                .any(|e| matches!(*e, "test" | "spec"))
+}
+
+/// Is the Rust source `content` compiled only for tests, through a
+/// `#![cfg(test)]` attribute at the top?
+fn is_test_only_rust(content: &str) -> bool {
+    content.lines().any(|line| line.trim() == "#![cfg(test)]")
+}
+
+/// The Rust source `content` without its `#[cfg(test)]` items, such as the
+/// usual `mod tests { ... }`, found at any depth of inline modules. Each is
+/// removed with its attributes and doc comments, and with the blank line
+/// after it when that would otherwise leave two in a row.
+pub fn strip_test_modules(content: &str) -> syn::Result<String> {
+    let file = syn::parse_file(content)?;
+    let mut ranges = Vec::new();
+    collect_test_items(&file.items, &mut ranges);
+    if ranges.is_empty() {
+        return Ok(content.to_string());
+    }
+
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let removed = |n: usize| {
+        ranges
+            .iter()
+            .any(|r: &LineRange| r.start <= n && r.end.is_none_or(|end| n <= end))
+    };
+    let blank = |i: usize| lines.get(i).is_none_or(|l| l.trim().is_empty());
+    let mut out = String::with_capacity(content.len());
+    for (i, line) in lines.iter().enumerate() {
+        let n = i + 1;
+        if removed(n) {
+            continue;
+        }
+        // A blank line right after a removed item, with a blank line (or
+        // nothing) before the item too.
+        if n > 1 && removed(n - 1) && line.trim().is_empty() {
+            let start = (1..n).rev().find(|&k| !removed(k));
+            if start.is_none_or(|k| blank(k - 1)) {
+                continue;
+            }
+        }
+        out.push_str(line);
+    }
+    Ok(out)
+}
+
+fn collect_test_items(items: &[Item], ranges: &mut Vec<LineRange>) {
+    for item in items {
+        let attrs = match item {
+            Item::Const(i) => &i.attrs,
+            Item::Enum(i) => &i.attrs,
+            Item::ExternCrate(i) => &i.attrs,
+            Item::Fn(i) => &i.attrs,
+            Item::ForeignMod(i) => &i.attrs,
+            Item::Impl(i) => &i.attrs,
+            Item::Macro(i) => &i.attrs,
+            Item::Mod(i) => &i.attrs,
+            Item::Static(i) => &i.attrs,
+            Item::Struct(i) => &i.attrs,
+            Item::Trait(i) => &i.attrs,
+            Item::TraitAlias(i) => &i.attrs,
+            Item::Type(i) => &i.attrs,
+            Item::Union(i) => &i.attrs,
+            Item::Use(i) => &i.attrs,
+            _ => continue,
+        };
+        let is_test = attrs.iter().any(|attr| {
+            attr.path().is_ident("cfg")
+                && attr
+                    .parse_args::<syn::Ident>()
+                    .is_ok_and(|ident| ident == "test")
+        });
+        if is_test {
+            ranges.push(lines_of(item));
+        } else if let Item::Mod(m) = item {
+            if let Some((_, items)) = &m.content {
+                collect_test_items(items, ranges);
+            }
+        }
+    }
+}
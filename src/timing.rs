@@ -0,0 +1,44 @@
+// src/timing.rs
+//! Per-phase wall-clock totals for `--timing`.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// The phases a run's time is attributed to.
+#[derive(Clone, Copy)]
+pub enum Phase {
+    Glob,
+    Walk,
+    Read,
+    Render,
+}
+
+const PHASES: [(Phase, &str); 4] = [
+    (Phase::Glob, "glob expansion"),
+    (Phase::Walk, "directory walking"),
+    (Phase::Read, "reading"),
+    (Phase::Render, "rendering"),
+];
+
+/// Nanoseconds spent in each phase, summed across threads.
+static TOTALS: [AtomicU64; 4] = [const { AtomicU64::new(0) }; 4];
+
+/// Run `f`, adding its duration to `phase`.
+pub fn time<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    let nanos = start.elapsed().as_nanos().try_into().unwrap_or(u64::MAX);
+    TOTALS[phase as usize].fetch_add(nanos, Ordering::Relaxed);
+    result
+}
+
+/// Print the totals to stderr. Parallel expansion is summed per thread, so
+/// the phases can add up to more than the run took.
+pub fn report(total: Duration) {
+    eprintln!("timing:");
+    for (phase, label) in PHASES {
+        let nanos = TOTALS[phase as usize].load(Ordering::Relaxed);
+        eprintln!("  {label:<18} {:>10.3?}", Duration::from_nanos(nanos));
+    }
+    eprintln!("  {:<18} {total:>10.3?}", "total");
+}
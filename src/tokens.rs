@@ -0,0 +1,8 @@
+// src/tokens.rs
+//! Rough token counts for fitting output into a model's context window.
+
+/// Estimated tokens in `text`, using the common rule of thumb of about four
+/// characters per token. Real tokenizers vary, so leave some headroom.
+pub fn estimate(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
@@ -0,0 +1,495 @@
+// src/transform.rs
+//! Display transforms applied to file content before printing.
+
+/// Make whitespace visible: tabs become `→` padded to four columns and
+/// trailing spaces become `·`.
+pub fn show_whitespace(content: &str) -> String {
+    let mut out = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        let (body, eol) = split_eol(line);
+        let trimmed = body.trim_end_matches(' ');
+        out.push_str(&trimmed.replace('\t', "→   "));
+        out.extend(std::iter::repeat_n('·', body.len() - trimmed.len()));
+        out.push_str(eol);
+    }
+    out
+}
+
+/// Prefix each line with its label, padded so the `|` gutter lines up.
+/// Lines past the end of `labels` get a blank gutter.
+pub fn prefix_lines(content: &str, labels: &[String]) -> String {
+    let width = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let mut out = String::with_capacity(content.len());
+    for (i, line) in content.split_inclusive('\n').enumerate() {
+        let label = labels.get(i).map_or("", String::as_str);
+        out.push_str(&format!("{label:<width$} | {line}"));
+    }
+    out
+}
+
+/// Number each line, counting from `first`, right-aligned to the width of
+/// the last line number.
+pub fn format_with_line_numbers(content: &str, first: usize) -> String {
+    let count = content.split_inclusive('\n').count();
+    let width = (first + count.max(1) - 1).to_string().len();
+    let mut out = String::with_capacity(content.len() + count * (width + 3));
+    for (i, line) in content.split_inclusive('\n').enumerate() {
+        out.push_str(&number_line(line, first + i, width));
+    }
+    out
+}
+
+/// `line` prefixed with its number `n`, right-aligned to `width`.
+pub fn number_line(line: &str, n: usize, width: usize) -> String {
+    format!("{n:>width$} | {line}")
+}
+
+/// Start every line with `prefix`.
+pub fn prefix_each(content: &str, prefix: &str) -> String {
+    let mut out = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        out.push_str(prefix);
+        out.push_str(line);
+    }
+    out
+}
+
+/// `json` re-serialized with two-space indentation, keeping key order and
+/// any trailing newline; `None` if it doesn't parse.
+pub fn pretty_json(json: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(json).ok()?;
+    let mut out = serde_json::to_string_pretty(&value).ok()?;
+    if json.ends_with('\n') {
+        out.push('\n');
+    }
+    Some(out)
+}
+
+/// Widest a `--render-tables` cell gets before it's cut short with `…`.
+const MAX_CELL_WIDTH: usize = 40;
+
+/// Widest a rendered table gets; columns past it are left out.
+const MAX_TABLE_WIDTH: usize = 160;
+
+/// CSV (or, with `delimiter` `\t`, TSV) `content` drawn as an ASCII table,
+/// its first row as the header, showing at most `max_rows` rows below it.
+/// Long cells are cut to fit a column and trailing columns that would make
+/// the table too wide are dropped, with a note under the table saying what
+/// was left out. `None` if `content` isn't well-formed: an unterminated or
+/// stray quote, or rows with differing numbers of fields.
+pub fn render_table(content: &str, delimiter: char, max_rows: usize) -> Option<String> {
+    let mut rows = parse_delimited(content, delimiter)?;
+    if rows.is_empty() {
+        return Some(content.to_string());
+    }
+    let hidden_rows = rows.len().saturating_sub(max_rows + 1);
+    rows.truncate(max_rows + 1);
+
+    let columns = rows[0].len();
+    let widths: Vec<usize> = (0..columns)
+        .map(|c| {
+            rows.iter()
+                .map(|r| r[c].chars().count())
+                .max()
+                .unwrap_or(0)
+                .min(MAX_CELL_WIDTH)
+        })
+        .collect();
+    // Always show the first column, however wide.
+    let mut shown = 1;
+    let mut total = widths[0] + 4;
+    while shown < columns && total + widths[shown] + 3 <= MAX_TABLE_WIDTH {
+        total += widths[shown] + 3;
+        shown += 1;
+    }
+    let widths = &widths[..shown];
+
+    let rule: String = widths
+        .iter()
+        .map(|w| format!("+{}", "-".repeat(w + 2)))
+        .chain(["+\n".to_string()])
+        .collect();
+    let line = |row: &[String]| -> String {
+        let mut out = String::new();
+        for (cell, &width) in row.iter().zip(widths) {
+            let cell = match cell.chars().count() > width {
+                true => cell.chars().take(width - 1).chain(['…']).collect(),
+                false => cell.clone(),
+            };
+            out.push_str(&format!("| {cell:<width$} "));
+        }
+        out.push_str("|\n");
+        out
+    };
+    let mut out = rule.clone();
+    out.push_str(&line(&rows[0]));
+    out.push_str(&rule);
+    for row in &rows[1..] {
+        out.push_str(&line(row));
+    }
+    if rows.len() > 1 {
+        out.push_str(&rule);
+    }
+    if shown < columns {
+        out.push_str(&format!("({} more columns not shown)\n", columns - shown));
+    }
+    if hidden_rows > 0 {
+        out.push_str(&format!("({hidden_rows} more rows not shown)\n"));
+    }
+    Some(out)
+}
+
+/// The rows of delimited `content`, RFC 4180 style: a field may be quoted,
+/// and then hold delimiters, newlines and doubled quotes. A blank last line
+/// isn't a row.
+fn parse_delimited(content: &str, delimiter: char) -> Option<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut chars = content.chars().peekable();
+    // Whether the field so far is a quoted one, and whether its closing quote
+    // has been seen.
+    let mut quoted = false;
+    let mut closed = false;
+    while let Some(c) = chars.next() {
+        if quoted && !closed {
+            if c != '"' {
+                field.push(c);
+            } else if chars.peek() == Some(&'"') {
+                chars.next();
+                field.push('"');
+            } else {
+                closed = true;
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() && !quoted => quoted = true,
+            '"' => return None,
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+                (quoted, closed) = (false, false);
+            }
+            c if c == delimiter => {
+                row.push(std::mem::take(&mut field));
+                (quoted, closed) = (false, false);
+            }
+            _ if closed => return None,
+            c => field.push(c),
+        }
+    }
+    if quoted && !closed {
+        return None;
+    }
+    if quoted || !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    let columns = rows.first().map_or(0, Vec::len);
+    rows.iter().all(|r| r.len() == columns).then_some(rows)
+}
+
+/// Split Markdown `content` into its frontmatter, a leading block between
+/// `---` lines (the closing one may also be `...`), and the rest. Content
+/// without frontmatter comes back as `("", content)`.
+pub fn split_frontmatter(content: &str) -> (&str, &str) {
+    let mut lines = content.split_inclusive('\n');
+    if lines.next().map(|l| split_eol(l).0) != Some("---") {
+        return ("", content);
+    }
+    let mut end = content.find('\n').map_or(content.len(), |i| i + 1);
+    for line in lines {
+        end += line.len();
+        if matches!(split_eol(line).0, "---" | "...") {
+            return content.split_at(end);
+        }
+    }
+    ("", content)
+}
+
+/// Markdown `content` with each prose paragraph re-wrapped to `width`
+/// columns, a word longer than that getting a line to itself. Block
+/// detection is minimal: headings, list items (and whatever directly follows
+/// them), fenced code, quotes, tables, HTML, indented lines and setext
+/// headings are all left exactly as they are, as is any paragraph with a
+/// hard line break. Frontmatter is kept too.
+pub fn reflow_markdown(content: &str, width: usize) -> String {
+    let (front, body) = split_frontmatter(content);
+    let lines: Vec<&str> = body.split_inclusive('\n').collect();
+    let mut out = String::with_capacity(content.len());
+    out.push_str(front);
+    let mut fence: Option<(char, usize)> = None;
+    let mut in_list = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        let body = split_eol(line).0;
+        i += 1;
+        if let Some((c, n)) = fence {
+            if closes_fence(body, c, n) {
+                fence = None;
+            }
+            out.push_str(line);
+            continue;
+        }
+        if let Some(open) = opening_fence(body) {
+            fence = Some(open);
+            in_list = false;
+        } else if body.trim().is_empty() {
+            in_list = false;
+        } else if is_list_item(body) {
+            in_list = true;
+        }
+        if fence.is_some() || in_list || !is_prose(body) {
+            out.push_str(line);
+            continue;
+        }
+
+        let start = i - 1;
+        while lines.get(i).is_some_and(|l| is_prose(split_eol(l).0)) {
+            i += 1;
+        }
+        let paragraph = &lines[start..i];
+        let setext = lines
+            .get(i)
+            .is_some_and(|l| is_setext_underline(split_eol(l).0));
+        let hard_break = paragraph[..paragraph.len() - 1].iter().any(|l| {
+            let body = split_eol(l).0;
+            body.ends_with("  ") || body.ends_with('\\')
+        });
+        if setext || hard_break {
+            paragraph.iter().for_each(|l| out.push_str(l));
+        } else {
+            out.push_str(&fill(paragraph, width));
+        }
+    }
+    out
+}
+
+/// The words of `paragraph` filled greedily into lines of at most `width`
+/// columns, ending the way its last line did.
+fn fill(paragraph: &[&str], width: usize) -> String {
+    let eol = match split_eol(paragraph[0]).1 {
+        "" => "\n",
+        eol => eol,
+    };
+    let last_eol = split_eol(paragraph[paragraph.len() - 1]).1;
+    let mut out = String::new();
+    let mut current = String::new();
+    for word in paragraph.iter().flat_map(|l| l.split_whitespace()) {
+        if current.is_empty() {
+            current.push_str(word);
+        } else if current.chars().count() + 1 + word.chars().count() <= width {
+            current.push(' ');
+            current.push_str(word);
+        } else {
+            out.push_str(&current);
+            out.push_str(eol);
+            current = word.to_string();
+        }
+    }
+    out.push_str(&current);
+    out.push_str(last_eol);
+    out
+}
+
+/// Could `line` be part of a paragraph to reflow? It can't start any other
+/// kind of block, and may not be indented.
+fn is_prose(line: &str) -> bool {
+    let other_block = line.trim().is_empty()
+        || line.starts_with(char::is_whitespace)
+        || line.starts_with(['#', '>', '<'])
+        || line.contains('|')
+        || line.starts_with('[') && line.contains("]:")
+        || is_list_item(line)
+        || opening_fence(line).is_some()
+        || is_setext_underline(line)
+        || is_thematic_break(line);
+    !other_block
+}
+
+/// `- `, `* `, `+ `, `1. ` or `1) ` at the start of `line`, after any
+/// indentation.
+fn is_list_item(line: &str) -> bool {
+    let t = line.trim_start();
+    let digits = t.len() - t.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let rest = match digits {
+        0 => t.strip_prefix(['-', '*', '+']),
+        1..=9 => t[digits..].strip_prefix(['.', ')']),
+        _ => None,
+    };
+    rest.is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\t']))
+}
+
+/// The fence character and length if `line` opens a fenced code block.
+fn opening_fence(line: &str) -> Option<(char, usize)> {
+    let t = line.trim_start();
+    if line.len() - t.len() > 3 {
+        return None;
+    }
+    let c = t.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let n = t.len() - t.trim_start_matches(c).len();
+    // A backtick fence's info string can't contain backticks.
+    (n >= 3 && !(c == '`' && t[n..].contains('`'))).then_some((c, n))
+}
+
+fn closes_fence(line: &str, c: char, n: usize) -> bool {
+    let t = line.trim_start();
+    let run = t.len() - t.trim_start_matches(c).len();
+    run >= n && t[run..].trim().is_empty()
+}
+
+/// A line of `=` or `-` under a line of text, making it a heading.
+fn is_setext_underline(line: &str) -> bool {
+    let t = line.trim();
+    !t.is_empty() && (t.chars().all(|c| c == '=') || t.chars().all(|c| c == '-'))
+}
+
+/// `***`, `---` or `___`, possibly spaced out.
+fn is_thematic_break(line: &str) -> bool {
+    let marks: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3 && ['*', '-', '_'].iter().any(|m| marks.iter().all(|c| c == m))
+}
+
+/// The content of every `<tag ...>…</tag>` block in `content`, as in the
+/// `<script>` of a `.vue` file, joined by blank lines; `None` if there are
+/// none. Blocks are found textually, so a tag can't nest inside itself.
+pub fn extract_sections(content: &str, tag: &str) -> Option<String> {
+    let open = format!("<{tag}");
+    let close = format!("</{tag}>");
+    let mut sections = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find(&open) {
+        let after = &rest[start + open.len()..];
+        // `<scripts>` isn't a `<script>` block.
+        if !after.starts_with(|c: char| c == '>' || c.is_whitespace()) {
+            rest = after;
+            continue;
+        }
+        // An unterminated block ends the search.
+        let Some(body_start) = after.find('>').map(|i| i + 1) else {
+            break;
+        };
+        let body = &after[body_start..];
+        let Some(end) = body.find(&close) else {
+            break;
+        };
+        sections.push(body[..end].trim_matches('\n'));
+        rest = &body[end + close.len()..];
+    }
+    if sections.is_empty() {
+        return None;
+    }
+    let mut out = sections.join("\n\n");
+    out.push('\n');
+    Some(out)
+}
+
+/// Best-effort removal of documentation, keyed off the file extension:
+/// `///` and `//!` comments in Rust, and docstrings in Python (a string
+/// literal opening a module, `def` or `class` body). This is textual, not a
+/// parse: Rust `/** */` blocks are kept, and a Python docstring is only
+/// recognized when it starts its own line. Other files are returned as is.
+pub fn strip_docstrings(content: &str, ext: &str) -> String {
+    match ext {
+        "rs" => content
+            .split_inclusive('\n')
+            .filter(|line| {
+                let t = line.trim_start();
+                !(t.starts_with("//!") || t.starts_with("///") && !t.starts_with("////"))
+            })
+            .collect(),
+        "py" | "pyi" => strip_python_docstrings(content),
+        _ => content.to_string(),
+    }
+}
+
+fn strip_python_docstrings(content: &str) -> String {
+    let mut out = String::with_capacity(content.len());
+    // A docstring may come next: at the top of the module, or once a `def`
+    // or `class` header (which may span lines) has ended with `:`.
+    let mut expect_doc = true;
+    let mut in_header = false;
+    // Brackets still open in the header so far.
+    let mut depth = 0i32;
+    // The closing quotes of the docstring being skipped.
+    let mut closing: Option<&str> = None;
+    for line in content.split_inclusive('\n') {
+        let t = line.trim();
+        if let Some(quote) = closing {
+            if t.contains(quote) {
+                closing = None;
+            }
+            continue;
+        }
+        if expect_doc {
+            if t.is_empty() || t.starts_with('#') {
+                out.push_str(line);
+                continue;
+            }
+            expect_doc = false;
+            let body = t.trim_start_matches(['r', 'R', 'u', 'U']);
+            if let Some(quote) = ["\"\"\"", "'''"].into_iter().find(|q| body.starts_with(q)) {
+                if !body[3..].contains(quote) {
+                    closing = Some(quote);
+                }
+                continue;
+            }
+        }
+        let starts_def = ["def ", "async def ", "class "]
+            .iter()
+            .any(|k| t.starts_with(k));
+        if starts_def || in_header {
+            let (code, delta) = python_code(t);
+            depth = if starts_def { delta } else { depth + delta };
+            // Open brackets or a trailing `\` carry the header on; otherwise
+            // it ends here, opening a block if it ends with `:` (and having
+            // put its body on the same line if not).
+            in_header = depth > 0 || code.ends_with('\\');
+            expect_doc = !in_header && code.ends_with(':');
+        }
+        out.push_str(line);
+    }
+    out
+}
+
+/// A line of Python without any trailing `#` comment or whitespace, and
+/// how many more brackets it opens than it closes. Quotes are followed
+/// within the line only, so a `#` or bracket in a string doesn't count.
+fn python_code(line: &str) -> (&str, i32) {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut depth = 0;
+    for (i, c) in line.char_indices() {
+        if let Some(q) = quote {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                _ if c == q => quote = None,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' => quote = Some(c),
+            '#' => return (line[..i].trim_end(), depth),
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    (line.trim_end(), depth)
+}
+
+/// Split a line into its body and its `\n` / `\r\n` terminator.
+fn split_eol(line: &str) -> (&str, &str) {
+    if let Some(body) = line.strip_suffix("\r\n") {
+        (body, "\r\n")
+    } else if let Some(body) = line.strip_suffix('\n') {
+        (body, "\n")
+    } else {
+        (line, "")
+    }
+}
@@ -0,0 +1,193 @@
+// src/tree.rs
+//! Rendering the resolved file set as a `tree`-style outline, or as JSON.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+use serde_json::{json, Value};
+
+/// A directory's contents. The same name can stand for a file and a
+/// directory at once, or for several files, when display paths from
+/// different inputs coincide; all of them are kept.
+#[derive(Default)]
+struct Dir {
+    dirs: BTreeMap<String, Dir>,
+    /// Each file's name and its index in the paths given.
+    files: Vec<(String, usize)>,
+}
+
+impl Dir {
+    /// Directories and files together, by name, a directory ahead of a
+    /// file of the same name; with `dirs_first`, all directories first.
+    fn entries(&self, dirs_first: bool) -> Vec<(&str, Entry<'_>)> {
+        let mut entries: Vec<(&str, Entry)> = self
+            .dirs
+            .iter()
+            .map(|(name, dir)| (name.as_str(), Entry::Dir(dir)))
+            .chain(
+                self.files
+                    .iter()
+                    .map(|(name, i)| (name.as_str(), Entry::File(*i))),
+            )
+            .collect();
+        // Stable, so files of the same name keep the order they came in.
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        if dirs_first {
+            entries.sort_by_key(|(_, entry)| matches!(entry, Entry::File(_)));
+        }
+        entries
+    }
+}
+
+enum Entry<'a> {
+    Dir(&'a Dir),
+    /// A file, by its index in the paths given.
+    File(usize),
+}
+
+/// An indented tree of `paths`, rooted at their deepest common directory.
+/// Chains of directories that each hold a single directory are folded onto
+/// one line, e.g. `a/b/c/`. With `dirs_first`, each level lists its
+/// directories ahead of its files.
+pub fn render(paths: &[PathBuf], dirs_first: bool) -> String {
+    let (root_name, root) = build(paths);
+    let mut out = root_name.display().to_string();
+    if out.is_empty() {
+        out.push('.');
+    } else if !out.ends_with('/') {
+        out.push('/');
+    }
+    out.push('\n');
+    render_children(&root, "", dirs_first, &mut out);
+    out
+}
+
+/// `paths` as nested JSON, rooted at their deepest common directory. A
+/// directory is `{"name", "type": "dir", "children"}` with its children
+/// sorted by name, and a file `{"name", "type": "file"}`, plus its `"size"`
+/// when `sizes` gives one per path.
+pub fn to_json(paths: &[PathBuf], sizes: Option<&[u64]>) -> Value {
+    let (root_name, root) = build(paths);
+    let name = match root_name.as_os_str().is_empty() {
+        true => ".".to_string(),
+        false => root_name.display().to_string(),
+    };
+    json!({ "name": name, "type": "dir", "children": json_children(&root, sizes) })
+}
+
+/// The contents of `paths` below their deepest common directory, along
+/// with that directory.
+fn build(paths: &[PathBuf]) -> (PathBuf, Dir) {
+    let parts: Vec<Vec<String>> = paths
+        .iter()
+        .map(|p| p.components().map(component_name).collect())
+        .collect();
+    let root_len = common_dir_len(&parts);
+
+    let mut root = Dir::default();
+    for (i, path) in parts.iter().enumerate() {
+        let (name, dirs) = path[root_len..].split_last().expect("paths name files");
+        let mut level = &mut root;
+        for dir in dirs {
+            level = level.dirs.entry(dir.clone()).or_default();
+        }
+        level.files.push((name.clone(), i));
+    }
+
+    let root_name: PathBuf = parts
+        .first()
+        .map(|p| p[..root_len].iter().collect())
+        .unwrap_or_default();
+    (root_name, root)
+}
+
+fn json_children(dir: &Dir, sizes: Option<&[u64]>) -> Vec<Value> {
+    dir.entries(false)
+        .into_iter()
+        .map(|(name, entry)| match entry {
+            Entry::File(i) => {
+                let mut file = json!({ "name": name, "type": "file" });
+                if let Some(sizes) = sizes {
+                    file["size"] = sizes[i].into();
+                }
+                file
+            }
+            Entry::Dir(contents) => json!({
+                "name": name,
+                "type": "dir",
+                "children": json_children(contents, sizes),
+            }),
+        })
+        .collect()
+}
+
+/// Order file paths by name, except that at the first component where they
+/// differ a directory sorts before a file.
+pub fn dirs_first(a: &Path, b: &Path) -> Ordering {
+    let (a, b): (Vec<_>, Vec<_>) = (a.components().collect(), b.components().collect());
+    let same = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let a_is_file = same + 1 >= a.len();
+    let b_is_file = same + 1 >= b.len();
+    a_is_file
+        .cmp(&b_is_file)
+        .then_with(|| a[same..].cmp(&b[same..]))
+}
+
+fn render_children(dir: &Dir, indent: &str, dirs_first: bool, out: &mut String) {
+    let entries = dir.entries(dirs_first);
+    let count = entries.len();
+    for (i, (name, entry)) in entries.into_iter().enumerate() {
+        let last = i + 1 == count;
+        let (branch, continuation) = match last {
+            true => ("└── ", "    "),
+            false => ("├── ", "│   "),
+        };
+        match entry {
+            Entry::File(_) => out.push_str(&format!("{indent}{branch}{name}\n")),
+            Entry::Dir(mut contents) => {
+                let mut label = name.to_string();
+                while let Some((child, grandchildren)) = single_child(contents) {
+                    label = format!("{label}/{child}");
+                    contents = grandchildren;
+                }
+                out.push_str(&format!("{indent}{branch}{label}/\n"));
+                render_children(
+                    contents,
+                    &format!("{indent}{continuation}"),
+                    dirs_first,
+                    out,
+                );
+            }
+        }
+    }
+}
+
+/// The only thing in `dir`, if that's a directory.
+fn single_child(dir: &Dir) -> Option<(&String, &Dir)> {
+    match (dir.dirs.len(), dir.files.len()) {
+        (1, 0) => dir.dirs.iter().next(),
+        _ => None,
+    }
+}
+
+/// How many leading components every path's parent directory shares.
+fn common_dir_len(parts: &[Vec<String>]) -> usize {
+    let Some((first, rest)) = parts.split_first() else {
+        return 0;
+    };
+    let mut len = first.len() - 1;
+    for other in rest {
+        len = len.min(other.len() - 1);
+        len = first[..len]
+            .iter()
+            .zip(other)
+            .take_while(|(a, b)| a == b)
+            .count();
+    }
+    len
+}
+
+fn component_name(c: Component) -> String {
+    c.as_os_str().to_string_lossy().into_owned()
+}
@@ -0,0 +1,93 @@
+// src/unbundle.rs
+//! Recreating files from a bundle printed with `--split-markers`.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// Read the bundle at `input` (stdin if `None`) and write each marked file
+/// beneath `dest`. Nothing is written unless every marker pairs up and, without
+/// `force`, none of the files already exist.
+pub fn run(input: Option<&Path>, dest: &Path, force: bool) -> anyhow::Result<()> {
+    let bundle = match input {
+        Some(path) => fs::read_to_string(path)?,
+        None => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text)?;
+            text
+        }
+    };
+
+    let files = parse(&bundle)?;
+    let mut targets = Vec::with_capacity(files.len());
+    for (name, _) in &files {
+        let target = dest.join(safe_relative(name)?);
+        if !force && target.exists() {
+            anyhow::bail!(
+                "`{}` already exists (use --force to overwrite)",
+                target.display()
+            );
+        }
+        targets.push(target);
+    }
+    for ((_, content), target) in files.iter().zip(&targets) {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(target, content)?;
+    }
+    Ok(())
+}
+
+/// The `(path, content)` pairs between matching markers; everything outside
+/// them (headers, separators) is ignored.
+fn parse(bundle: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let mut files = Vec::new();
+    let mut current: Option<(String, String)> = None;
+    for line in bundle.split_inclusive('\n') {
+        let marker = line.trim_end_matches('\n');
+        match &mut current {
+            None => {
+                if let Some(name) = between(marker, "<<<FILE: ") {
+                    current = Some((name.to_string(), String::new()));
+                } else if between(marker, "<<<END FILE: ").is_some()
+                    || between(marker, "<<<END FILE NOEOL: ").is_some()
+                {
+                    anyhow::bail!("`{marker}` has no matching start marker");
+                }
+            }
+            Some((name, content)) => {
+                if between(marker, "<<<END FILE: ") == Some(name.as_str()) {
+                    files.push(current.take().expect("inside a file"));
+                } else if between(marker, "<<<END FILE NOEOL: ") == Some(name.as_str()) {
+                    // The printer added a newline before the end marker.
+                    content.pop();
+                    files.push(current.take().expect("inside a file"));
+                } else {
+                    content.push_str(line);
+                }
+            }
+        }
+    }
+    if let Some((name, _)) = current {
+        anyhow::bail!("`{name}` has no end marker");
+    }
+    Ok(files)
+}
+
+/// The text between `prefix` and a closing `>>>` that ends `line`.
+fn between<'a>(line: &'a str, prefix: &str) -> Option<&'a str> {
+    line.strip_prefix(prefix)?.strip_suffix(">>>")
+}
+
+/// `name` as a path that stays beneath the destination directory.
+fn safe_relative(name: &str) -> anyhow::Result<PathBuf> {
+    let path = PathBuf::from(name);
+    if !path
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        anyhow::bail!("refusing to write `{name}` outside the target directory");
+    }
+    Ok(path)
+}
@@ -0,0 +1,110 @@
+// src/watch.rs
+//! `--watch`: re-running the bundle whenever the files behind it change.
+
+use std::collections::HashSet;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{EventKind, RecursiveMode, Watcher};
+
+/// How long the files must stay quiet before a re-run, so that one save
+/// (often several writes and a rename) prints once.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+enum Signal {
+    Changed(notify::Event),
+    Stop,
+}
+
+/// The directories being watched, for a round to replace before printing
+/// so that nothing changing while it prints goes unnoticed.
+pub struct WatchSet {
+    watcher: notify::RecommendedWatcher,
+    dirs: HashSet<PathBuf>,
+}
+
+impl WatchSet {
+    /// Watch exactly `dirs`, non-recursively: new files in them are picked
+    /// up, but nothing deeper.
+    pub fn update(&mut self, dirs: Vec<PathBuf>) -> anyhow::Result<()> {
+        let dirs: HashSet<PathBuf> = dirs.into_iter().collect();
+        for gone in self.dirs.difference(&dirs) {
+            let _ = self.watcher.unwatch(gone);
+        }
+        for dir in dirs.difference(&self.dirs) {
+            self.watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(|e| anyhow::anyhow!("cannot watch `{}`: {e}", dir.display()))?;
+        }
+        self.dirs = dirs;
+        Ok(())
+    }
+}
+
+/// Call `round` now and again after every change to the directories it
+/// watches, until Ctrl-C. A failing round is reported and leaves the set as
+/// it was, unless it's the first. Changes to `ignored` (the --output file)
+/// never trigger a round.
+pub fn run(
+    ignored: Option<&Path>,
+    mut round: impl FnMut(&mut WatchSet) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let stop = tx.clone();
+    ctrlc::set_handler(move || {
+        let _ = stop.send(Signal::Stop);
+    })
+    .map_err(|e| anyhow::anyhow!("cannot handle Ctrl-C: {e}"))?;
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            let _ = tx.send(Signal::Changed(event));
+        }
+    })
+    .map_err(|e| anyhow::anyhow!("cannot watch files: {e}"))?;
+    let mut set = WatchSet {
+        watcher,
+        dirs: HashSet::new(),
+    };
+
+    let mut first = true;
+    loop {
+        if !first && io::stdout().is_terminal() {
+            print!("\x1b[2J\x1b[H");
+        }
+        match round(&mut set) {
+            Ok(()) => {}
+            Err(e) if first => return Err(e),
+            Err(e) => eprintln!("{e}"),
+        }
+        first = false;
+        let _ = io::stdout().flush();
+
+        let ignored = ignored.and_then(|p| p.canonicalize().ok());
+        // Wait for a change that matters, then for things to settle.
+        loop {
+            match rx.recv() {
+                Ok(Signal::Changed(event)) if triggers(&event, ignored.as_deref()) => break,
+                Ok(Signal::Changed(_)) => {}
+                Ok(Signal::Stop) | Err(_) => return Ok(()),
+            }
+        }
+        loop {
+            match rx.recv_timeout(DEBOUNCE) {
+                Ok(Signal::Changed(_)) => {}
+                Ok(Signal::Stop) => return Ok(()),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+/// Does `event` call for a re-run? Reads don't, and neither do writes to
+/// the `ignored` file alone.
+fn triggers(event: &notify::Event, ignored: Option<&Path>) -> bool {
+    if matches!(event.kind, EventKind::Access(_)) {
+        return false;
+    }
+    event.paths.iter().any(|p| Some(p.as_path()) != ignored)
+}